@@ -0,0 +1,219 @@
+//! Compares the work-stealing ThreadPool with the design it replaced, in
+//! which every worker took jobs from one `Mutex<mpsc::Receiver>`.
+//!
+//! Run with `cargo bench`.
+
+/* extern crate definitions */
+extern crate hello;
+
+/* extern create usings */
+use hello::ThreadPool;
+
+/* System includes */
+use std::thread;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const THREADS: usize = 4;
+const TINY_JOBS: usize = 200_000;
+const LATENCY_JOBS: usize = 5_000;
+const TREE_DEPTH: u32 = 16;
+
+/** The previous ThreadPool, stripped of its logging. */
+struct ChannelPool {
+   workers: Vec<thread::JoinHandle<()>>,
+   sender: Option<mpsc::Sender<Box<dyn FnOnce() + Send>>>,
+}
+
+impl ChannelPool {
+   fn new(size: usize) -> ChannelPool {
+      let (sender, receiver) = mpsc::channel::<Box<dyn FnOnce() + Send>>();
+      let receiver = Arc::new(Mutex::new(receiver));
+
+      let workers = (0..size).map(|_| {
+         let receiver = Arc::clone(&receiver);
+
+         thread::spawn(move || loop {
+            let job = receiver.lock().unwrap().recv();
+
+            match job {
+               Ok(job) => job(),
+               Err(_) => break,
+            }
+         })
+      }).collect();
+
+      ChannelPool {
+         workers,
+         sender: Some(sender),
+      }
+   }
+
+   fn execute<F: FnOnce() + Send + 'static>(&self, f: F) {
+      self.sender.as_ref().unwrap().send(Box::new(f)).unwrap();
+   }
+}
+
+impl Drop for ChannelPool {
+   fn drop(&mut self) {
+      self.sender.take();
+
+      for worker in self.workers.drain(..) {
+         worker.join().unwrap();
+      }
+   }
+}
+
+/** What the benchmarks need from a pool. */
+trait Pool {
+   fn run<F: FnOnce() + Send + 'static>(&self, f: F);
+}
+
+impl Pool for ChannelPool {
+   fn run<F: FnOnce() + Send + 'static>(&self, f: F) {
+      self.execute(f);
+   }
+}
+
+impl Pool for ThreadPool {
+   fn run<F: FnOnce() + Send + 'static>(&self, f: F) {
+      self.execute(f);
+   }
+}
+
+/// Submits `TINY_JOBS` jobs that each bump a counter and waits for them all.
+fn throughput<P: Pool>(pool: &P) -> Duration {
+   let count = Arc::new(AtomicUsize::new(0));
+   let start = Instant::now();
+
+   for _ in 0..TINY_JOBS {
+      let count = Arc::clone(&count);
+
+      pool.run(move || { count.fetch_add(1, Ordering::Relaxed); });
+   }
+
+   while count.load(Ordering::Relaxed) < TINY_JOBS {
+      thread::yield_now();
+   }
+
+   start.elapsed()
+}
+
+/// Measures how long jobs wait between submission and starting, with the
+/// pool otherwise idle. Returns the median and the 99th percentile.
+fn latency<P: Pool>(pool: &P) -> (Duration, Duration) {
+   let (sender, receiver) = mpsc::channel();
+   let mut waits = Vec::with_capacity(LATENCY_JOBS);
+
+   for _ in 0..LATENCY_JOBS {
+      let sender = sender.clone();
+      let submitted = Instant::now();
+
+      pool.run(move || sender.send(submitted.elapsed()).unwrap());
+      waits.push(receiver.recv().unwrap());
+   }
+
+   waits.sort();
+
+   (waits[waits.len() / 2], waits[waits.len() * 99 / 100])
+}
+
+/// Submits `TINY_JOBS` jobs from `THREADS` threads at once, each bumping a
+/// counter, and waits for them all.
+fn submitters(pool: &ThreadPool) -> Duration {
+   let count = Arc::new(AtomicUsize::new(0));
+   let start = Instant::now();
+
+   let threads: Vec<thread::JoinHandle<()>> = (0..THREADS).map(|_| {
+      let (spawner, count) = (pool.spawner(), Arc::clone(&count));
+
+      thread::spawn(move || for _ in 0..TINY_JOBS / THREADS {
+         let count = Arc::clone(&count);
+
+         spawner.execute(move || { count.fetch_add(1, Ordering::Relaxed); });
+      })
+   }).collect();
+
+   for thread in threads {
+      thread.join().unwrap();
+   }
+
+   while count.load(Ordering::Relaxed) < TINY_JOBS / THREADS * THREADS {
+      thread::yield_now();
+   }
+
+   start.elapsed()
+}
+
+/// Runs a binary tree of jobs where every job submits its two children.
+fn tree(pool: &ThreadPool) -> Duration {
+   fn node(spawner: hello::Spawner, depth: u32, leaves: Arc<AtomicUsize>) {
+      if depth == 0 {
+         leaves.fetch_add(1, Ordering::Relaxed);
+
+         return;
+      }
+
+      for _ in 0..2 {
+         let (child, leaves) = (spawner.clone(), Arc::clone(&leaves));
+
+         spawner.execute(move || node(child, depth - 1, leaves));
+      }
+   }
+
+   let leaves = Arc::new(AtomicUsize::new(0));
+   let (spawner, counted) = (pool.spawner(), Arc::clone(&leaves));
+   let start = Instant::now();
+
+   pool.execute(move || node(spawner, TREE_DEPTH, counted));
+
+   while leaves.load(Ordering::Relaxed) < 1 << TREE_DEPTH {
+      thread::yield_now();
+   }
+
+   start.elapsed()
+}
+
+fn report<P: Pool>(name: &str, pool: &P) {
+   let elapsed = throughput(pool);
+   let (median, p99) = latency(pool);
+
+   println!(
+      "{:<14} {:>10.0} jobs/s {:>10.1?} median wait {:>10.1?} p99 wait",
+      name,
+      TINY_JOBS as f64 / elapsed.as_secs_f64(),
+      median,
+      p99
+   );
+}
+
+fn main() {
+   println!("{} threads, {} tiny jobs, {} latency samples", THREADS, TINY_JOBS, LATENCY_JOBS);
+
+   report("channel", &ChannelPool::new(THREADS));
+
+   let pool = ThreadPool::new(THREADS);
+
+   report("work-stealing", &pool);
+
+   let elapsed = submitters(&pool);
+
+   println!(
+      "{:<14} {:>10.0} jobs/s ({} threads submitting at once)",
+      "submitters",
+      (TINY_JOBS / THREADS * THREADS) as f64 / elapsed.as_secs_f64(),
+      THREADS
+   );
+
+   let elapsed = tree(&pool);
+
+   println!(
+      "{:<14} {:>10.0} jobs/s ({} jobs submitted by jobs)",
+      "job tree",
+      ((2 << TREE_DEPTH) - 1) as f64 / elapsed.as_secs_f64(),
+      (2 << TREE_DEPTH) - 2
+   );
+}
@@ -0,0 +1,188 @@
+//! Holds many slow clients open against each server mode, and times a fast
+//! client meanwhile. Each slow client sends the start of a request and then
+//! a byte of a header every so often, never finishing it.
+//!
+//! Run with `cargo bench --bench slow_clients`. `SLOW_CLIENTS` sets how many
+//! (10000 by default); the servers run in a child process, so that neither
+//! process needs a descriptor for both ends of every connection.
+
+/* extern crate definitions */
+extern crate hello;
+
+/* extern create usings */
+use hello::http::Response;
+use hello::router::Router;
+use hello::server::{self, ConnectionOptions, ServerOptions};
+use hello::reactor;
+
+/* System includes */
+use std::env;
+use std::io;
+use std::thread;
+
+use std::io::prelude::*;
+use std::io::BufReader;
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::process::{Command, Stdio};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const THREADS: usize = 4;
+const FAST_REQUESTS: usize = 200;
+const TRICKLE_INTERVAL: Duration = Duration::from_millis(500);
+
+fn main() {
+   let args: Vec<String> = env::args().collect();
+
+   if args.get(1).map(String::as_str) == Some("--serve") {
+      return serve(&args[2]);
+   }
+
+   let clients = env::var("SLOW_CLIENTS").ok().and_then(|clients| clients.parse().ok()).unwrap_or(10_000);
+
+   println!("{} slow clients, {} threads, {} fast requests", clients, THREADS, FAST_REQUESTS);
+   println!("{:<10} {:>9} {:>8} {:>8} {:>8} {:>10} {:>10}", "mode", "connected", "ok", "503", "failed", "median", "p99");
+
+   for mode in &["threaded", "reactor"] {
+      run(mode, clients).unwrap_or_else(|e| println!("{:<10} failed: {}", mode, e));
+   }
+}
+
+/// Serves `/` in the child process until its stdin closes, printing the
+/// port first.
+fn serve(mode: &str) {
+   let mut router = Router::new();
+
+   router.get("/", |_, _| Response::new(200).body("hello"));
+
+   let options = ServerOptions {
+      threads: THREADS,
+      connection: ConnectionOptions {
+         idle_timeout: Duration::from_secs(60),
+         ..ConnectionOptions::default()
+      },
+      drain_timeout: Duration::from_secs(0),
+      ..ServerOptions::default()
+   };
+
+   let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+   println!("{}", listener.local_addr().unwrap().port());
+
+   let (shutdown, run): (_, Box<dyn FnOnce()>) = match mode {
+      "reactor" => {
+         let server = reactor::Server::new(listener, router, options).unwrap();
+
+         (server.shutdown_handle().unwrap(), Box::new(move || { server.run(); }))
+      },
+      _ => {
+         let server = server::Server::new(listener, router, options);
+
+         (server.shutdown_handle().unwrap(), Box::new(move || { server.run(); }))
+      },
+   };
+
+   thread::spawn(move || {
+      let _ = io::stdin().read_to_end(&mut Vec::new());
+
+      shutdown.trigger();
+   });
+
+   run();
+}
+
+fn run(mode: &str, clients: usize) -> io::Result<()> {
+   let mut child = Command::new(env::current_exe()?)
+      .args(["--serve", mode])
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .spawn()?;
+
+   let mut port = String::new();
+
+   BufReader::new(child.stdout.take().unwrap()).read_line(&mut port)?;
+
+   let address: SocketAddr = format!("127.0.0.1:{}", port.trim()).parse().unwrap();
+
+   let mut slow = Vec::with_capacity(clients);
+
+   for _ in 0..clients {
+      match TcpStream::connect(address) {
+         Ok(mut client) => {
+            if client.write_all(b"GET / HTTP/1.1\r\nX-Slow: ").is_ok() {
+               slow.push(client);
+            }
+         },
+         Err(_) => break,
+      }
+   }
+
+   let stop = Arc::new(AtomicBool::new(false));
+   let trickle = {
+      let stop = Arc::clone(&stop);
+
+      thread::spawn(move || {
+         while !stop.load(Ordering::SeqCst) {
+            slow.retain_mut(|client| client.write_all(b"x").is_ok());
+            thread::sleep(TRICKLE_INTERVAL);
+         }
+
+         slow.len()
+      })
+   };
+
+   // Let the turned-away clients find out.
+   thread::sleep(TRICKLE_INTERVAL * 2);
+
+   let (mut ok, mut busy, mut failed) = (0, 0, 0);
+   let mut latencies = Vec::with_capacity(FAST_REQUESTS);
+
+   for _ in 0..FAST_REQUESTS {
+      let start = Instant::now();
+
+      match fetch(address) {
+         Ok(ref response) if response.starts_with("HTTP/1.1 200") => ok += 1,
+         Ok(ref response) if response.starts_with("HTTP/1.1 503") => busy += 1,
+         _ => failed += 1,
+      }
+
+      latencies.push(start.elapsed());
+   }
+
+   stop.store(true, Ordering::SeqCst);
+
+   let connected = trickle.join().unwrap();
+
+   latencies.sort();
+
+   println!(
+      "{:<10} {:>9} {:>8} {:>8} {:>8} {:>10.2?} {:>10.2?}",
+      mode,
+      connected,
+      ok,
+      busy,
+      failed,
+      latencies[latencies.len() / 2],
+      latencies[latencies.len() * 99 / 100]
+   );
+
+   drop(child.stdin.take());
+   child.kill()?;
+   child.wait()?;
+
+   Ok(())
+}
+
+fn fetch(address: SocketAddr) -> io::Result<String> {
+   let mut client = TcpStream::connect(address)?;
+   let mut response = String::new();
+
+   client.set_read_timeout(Some(Duration::from_secs(5)))?;
+   client.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")?;
+   client.read_to_string(&mut response)?;
+
+   Ok(response)
+}
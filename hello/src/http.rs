@@ -0,0 +1,785 @@
+/* System includes */
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Longest request line plus headers that is accepted.
+pub const MAX_HEAD: usize = 8 * 1024;
+
+/// Largest request body that is accepted.
+pub const MAX_BODY: usize = 1024 * 1024;
+
+/** HTTP version of a request. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+   Http10,
+   Http11,
+}
+
+/** A parsed HTTP request. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+   pub method: String,
+   pub target: String,
+   pub version: Version,
+   pub headers: Vec<(String, String)>,
+   pub body: Vec<u8>,
+   /// Fields sent after a chunked body. They are kept apart from `headers`
+   /// so that they cannot change how the request or connection is handled.
+   pub trailers: Vec<(String, String)>,
+}
+
+impl Request {
+   /// Returns the value of the first header called `name`, ignoring case.
+   pub fn header(&self, name: &str) -> Option<&str> {
+      self.headers.iter()
+         .find(|(header, _)| header.eq_ignore_ascii_case(name))
+         .map(|(_, value)| value.as_str())
+   }
+
+   /// Returns the path of the target, without its query string.
+   pub fn path(&self) -> &str {
+      match self.target.find('?') {
+         Some(i) => &self.target[..i],
+         None => &self.target,
+      }
+   }
+}
+
+/** Why a request could not be read. */
+#[derive(Debug)]
+pub enum ParseError {
+   /// The request does not follow HTTP/1.1.
+   Malformed(&'static str),
+   /// The request line and headers are longer than `MAX_HEAD`.
+   HeadTooLarge,
+   /// The body is longer than `MAX_BODY`.
+   BodyTooLarge,
+   /// The request is for a version other than HTTP/1.0 or HTTP/1.1.
+   UnsupportedVersion,
+   /// The connection closed in the middle of a request.
+   UnexpectedEof,
+   /// The request was not complete within the request timeout.
+   Timeout,
+   /// Reading from the connection failed.
+   Io(io::Error),
+}
+
+impl ParseError {
+   /// Returns the response telling the client what was wrong, or `None` if
+   /// the client cannot be answered any more.
+   pub fn response(&self) -> Option<Response> {
+      let status = match *self {
+         ParseError::Malformed(_) => 400,
+         ParseError::HeadTooLarge => 431,
+         ParseError::BodyTooLarge => 413,
+         ParseError::UnsupportedVersion => 505,
+         ParseError::Timeout => 408,
+         ParseError::UnexpectedEof | ParseError::Io(_) => return None,
+      };
+
+      Some(Response::new(status)
+         .header("Content-Type", "text/plain; charset=utf-8")
+         .header("Connection", "close")
+         .body(format!("{}\n", self)))
+   }
+}
+
+impl fmt::Display for ParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match *self {
+         ParseError::Malformed(reason) => write!(f, "Bad request: {}", reason),
+         ParseError::HeadTooLarge => write!(f, "Request header fields too large"),
+         ParseError::BodyTooLarge => write!(f, "Request body too large"),
+         ParseError::UnsupportedVersion => write!(f, "HTTP version not supported"),
+         ParseError::UnexpectedEof => write!(f, "Connection closed in the middle of a request"),
+         ParseError::Timeout => write!(f, "Request not received in time"),
+         ParseError::Io(ref e) => write!(f, "{}", e),
+      }
+   }
+}
+
+impl From<io::Error> for ParseError {
+   fn from(e: io::Error) -> ParseError {
+      ParseError::Io(e)
+   }
+}
+
+/// Parses the request at the start of `buf`.
+///
+/// ## Input:
+///
+/// * `buf` - The bytes received so far.
+///
+/// ## Returns
+///
+/// * `Ok(Some((request, len)))` - A complete request taking the first `len`
+///   bytes of `buf`. Any bytes after those belong to the next request.
+/// * `Ok(None)` - `buf` holds the start of a request; more is needed.
+/// * `Err(_)` - `buf` can never become a valid request.
+pub fn parse(buf: &[u8]) -> Result<Option<(Request, usize)>, ParseError> {
+   let head_len = match find(buf, b"\r\n\r\n") {
+      Some(i) if i + 4 > MAX_HEAD => return Err(ParseError::HeadTooLarge),
+      Some(i) => i + 4,
+      None if buf.len() >= MAX_HEAD => return Err(ParseError::HeadTooLarge),
+      None => return Ok(None),
+   };
+
+   let head = match std::str::from_utf8(&buf[..head_len - 4]) {
+      Ok(head) => head,
+      Err(_) => return Err(ParseError::Malformed("request head is not UTF-8")),
+   };
+
+   let mut lines = head.split("\r\n");
+
+   let (method, target, version) = parse_request_line(lines.next().unwrap_or(""))?;
+
+   let mut headers = Vec::new();
+
+   for line in lines {
+      headers.push(parse_header(line)?);
+   }
+
+   let mut request = Request {
+      method: method.to_string(),
+      target: target.to_string(),
+      version,
+      headers,
+      body: Vec::new(),
+      trailers: Vec::new(),
+   };
+
+   let body = match body_length(&request)? {
+      BodyLength::Chunked => parse_chunked(&buf[head_len..], &mut request.trailers)?,
+      BodyLength::Fixed(len) if buf.len() - head_len >= len => {
+         Some((buf[head_len..head_len + len].to_vec(), len))
+      },
+      BodyLength::Fixed(_) => None,
+   };
+
+   Ok(body.map(|(body, len)| {
+      request.body = body;
+
+      (request, head_len + len)
+   }))
+}
+
+fn parse_request_line(line: &str) -> Result<(&str, &str, Version), ParseError> {
+   let mut parts = line.split(' ');
+
+   let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+      (Some(method), Some(target), Some(version), None) => (method, target, version),
+      _ => return Err(ParseError::Malformed("invalid request line")),
+   };
+
+   if !is_token(method) {
+      return Err(ParseError::Malformed("invalid method"));
+   }
+
+   if target.is_empty() || target.bytes().any(|b| b <= b' ' || b == 0x7f) {
+      return Err(ParseError::Malformed("invalid request target"));
+   }
+
+   let version = match version {
+      "HTTP/1.1" => Version::Http11,
+      "HTTP/1.0" => Version::Http10,
+      _ if is_http_version(version) => return Err(ParseError::UnsupportedVersion),
+      _ => return Err(ParseError::Malformed("invalid HTTP version")),
+   };
+
+   Ok((method, target, version))
+}
+
+fn parse_header(line: &str) -> Result<(String, String), ParseError> {
+   let colon = match line.find(':') {
+      Some(colon) => colon,
+      None => return Err(ParseError::Malformed("header without a colon")),
+   };
+
+   let name = &line[..colon];
+
+   // This also rejects obsolete line folding, which starts with whitespace.
+   if !is_token(name) {
+      return Err(ParseError::Malformed("invalid header name"));
+   }
+
+   let value = line[colon + 1..].trim_matches(|c| c == ' ' || c == '\t');
+
+   if value.bytes().any(|b| (b < b' ' && b != b'\t') || b == 0x7f) {
+      return Err(ParseError::Malformed("invalid header value"));
+   }
+
+   Ok((name.to_string(), value.to_string()))
+}
+
+enum BodyLength {
+   Fixed(usize),
+   Chunked,
+}
+
+fn body_length(request: &Request) -> Result<BodyLength, ParseError> {
+   let encodings: Vec<&str> = request.headers.iter()
+      .filter(|(name, _)| name.eq_ignore_ascii_case("Transfer-Encoding"))
+      .flat_map(|(_, value)| value.split(','))
+      .map(|coding| coding.trim())
+      .collect();
+
+   // Transfer-Encoding overrides Content-Length.
+   if !encodings.is_empty() {
+      return match encodings.last() {
+         Some(coding) if coding.eq_ignore_ascii_case("chunked") => Ok(BodyLength::Chunked),
+         _ => Err(ParseError::Malformed("request body is not chunked")),
+      };
+   }
+
+   let mut length = None;
+
+   for (name, value) in &request.headers {
+      if !name.eq_ignore_ascii_case("Content-Length") {
+         continue;
+      }
+
+      if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+         return Err(ParseError::Malformed("invalid Content-Length"));
+      }
+
+      let value: usize = match value.parse() {
+         Ok(value) => value,
+         Err(_) => return Err(ParseError::BodyTooLarge),
+      };
+
+      if length.is_some_and(|length| length != value) {
+         return Err(ParseError::Malformed("conflicting Content-Length headers"));
+      }
+
+      length = Some(value);
+   }
+
+   match length {
+      Some(length) if length > MAX_BODY => Err(ParseError::BodyTooLarge),
+      Some(length) => Ok(BodyLength::Fixed(length)),
+      None => Ok(BodyLength::Fixed(0)),
+   }
+}
+
+/// Decodes a chunked body at the start of `buf`, adding any trailer fields
+/// to `trailers`. Returns the body and the number of bytes it took, or
+/// `None` if the body is not complete yet.
+fn parse_chunked(buf: &[u8], trailers: &mut Vec<(String, String)>) -> Result<Option<(Vec<u8>, usize)>, ParseError> {
+   let mut body = Vec::new();
+   let mut pos = 0;
+
+   loop {
+      let line_len = match find(&buf[pos..], b"\r\n") {
+         Some(len) => len,
+         None if buf.len() - pos > MAX_HEAD => return Err(ParseError::Malformed("chunk size line too long")),
+         None => return Ok(None),
+      };
+
+      let line = &buf[pos..pos + line_len];
+      let size = &line[..line.iter().position(|&b| b == b';').unwrap_or(line.len())];
+      let size = std::str::from_utf8(size).ok()
+         .map(|size| size.trim_matches(|c| c == ' ' || c == '\t'))
+         .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+         .and_then(|size| usize::from_str_radix(size, 16).ok());
+
+      let size = match size {
+         Some(size) => size,
+         None => return Err(ParseError::Malformed("invalid chunk size")),
+      };
+
+      // The client picks `size`, so it is bounded before anything adds to
+      // it.
+      if size > MAX_BODY - body.len() {
+         return Err(ParseError::BodyTooLarge);
+      }
+
+      pos += line_len + 2;
+
+      if size == 0 {
+         break;
+      }
+
+      let end = match pos.checked_add(size).and_then(|end| end.checked_add(2)) {
+         Some(end) if end <= buf.len() => end,
+         Some(_) => return Ok(None),
+         None => return Err(ParseError::BodyTooLarge),
+      };
+
+      if &buf[end - 2..end] != b"\r\n" {
+         return Err(ParseError::Malformed("chunk data not followed by CRLF"));
+      }
+
+      body.extend_from_slice(&buf[pos..end - 2]);
+      pos = end;
+   }
+
+   // Trailer fields, up to an empty line.
+   loop {
+      let line_len = match find(&buf[pos..], b"\r\n") {
+         Some(len) => len,
+         None if buf.len() - pos > MAX_HEAD => return Err(ParseError::HeadTooLarge),
+         None => return Ok(None),
+      };
+
+      let line = &buf[pos..pos + line_len];
+      pos += line_len + 2;
+
+      if line.is_empty() {
+         return Ok(Some((body, pos)));
+      }
+
+      match std::str::from_utf8(line) {
+         Ok(line) => trailers.push(parse_header(line)?),
+         Err(_) => return Err(ParseError::Malformed("trailer is not UTF-8")),
+      }
+   }
+}
+
+/** Reads requests one after another from a stream. */
+pub struct RequestReader<R> {
+   inner: R,
+   buf: Vec<u8>,
+   /// When the first byte of the request being read was seen.
+   started: Option<Instant>,
+   timeout: Option<Duration>,
+}
+
+impl<R: Read> RequestReader<R> {
+   /// Create a new RequestReader.
+   ///
+   /// ## Input:
+   ///
+   /// * `inner` - The stream to read requests from.
+   pub fn new(inner: R) -> RequestReader<R> {
+      RequestReader {
+         inner,
+         buf: Vec::new(),
+         started: None,
+         timeout: None,
+      }
+   }
+
+   /// Gives every request `timeout` to arrive in full, head and body, from
+   /// its first byte. After that `read_request` fails with
+   /// `ParseError::Timeout`, however steadily the bytes are coming.
+   pub fn request_timeout(mut self, timeout: Duration) -> RequestReader<R> {
+      self.timeout = Some(timeout);
+      self
+   }
+
+   /// Reads the next request, however many reads it takes. Bytes read past
+   /// the end of the request are kept for the next call.
+   ///
+   /// Returns `Ok(None)` if the stream ended cleanly before a new request.
+   pub fn read_request(&mut self) -> Result<Option<Request>, ParseError> {
+      let mut chunk = [0; 4096];
+
+      loop {
+         if !self.buf.is_empty() {
+            if let Some((request, len)) = parse(&self.buf)? {
+               self.buf.drain(..len);
+               self.started = None;
+
+               return Ok(Some(request));
+            }
+
+            let started = *self.started.get_or_insert_with(Instant::now);
+
+            if self.timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+               return Err(ParseError::Timeout);
+            }
+         }
+
+         let read = match self.inner.read(&mut chunk) {
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(ParseError::Io(e)),
+         };
+
+         if read == 0 {
+            return if self.buf.is_empty() {
+               Ok(None)
+            } else {
+               Err(ParseError::UnexpectedEof)
+            };
+         }
+
+         self.buf.extend_from_slice(&chunk[..read]);
+      }
+   }
+
+   /// Returns how many bytes of the next request have been read already.
+   pub fn buffered(&self) -> usize {
+      self.buf.len()
+   }
+
+   pub fn get_ref(&self) -> &R {
+      &self.inner
+   }
+
+   pub fn get_mut(&mut self) -> &mut R {
+      &mut self.inner
+   }
+}
+
+/** An HTTP response. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+   pub status: u16,
+   pub headers: Vec<(String, String)>,
+   pub body: Vec<u8>,
+   /// How long to hold the response back before sending it.
+   pub delay: Option<Duration>,
+}
+
+impl Response {
+   /// Create a new Response with an empty body.
+   ///
+   /// ## Input:
+   ///
+   /// * `status` - The status code of the response.
+   pub fn new(status: u16) -> Response {
+      Response {
+         status,
+         headers: Vec::new(),
+         body: Vec::new(),
+         delay: None,
+      }
+   }
+
+   pub fn header(mut self, name: &str, value: &str) -> Response {
+      self.headers.push((name.to_string(), value.to_string()));
+
+      self
+   }
+
+   pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Response {
+      self.body = body.into();
+
+      self
+   }
+
+   /// Sends the response only once `delay` has passed. Unlike a handler
+   /// that sleeps, this does not hold a pool thread in `reactor::Server`,
+   /// which leaves the wait to the pool's timer.
+   pub fn delay(mut self, delay: Duration) -> Response {
+      self.delay = Some(delay);
+
+      self
+   }
+
+   /// Writes the response, adding `Content-Length` unless the status
+   /// forbids a body.
+   pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+      let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+
+      for (name, value) in &self.headers {
+         head.push_str(&format!("{}: {}\r\n", name, value));
+      }
+
+      if self.status != 204 && self.status != 304 {
+         head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+      }
+
+      head.push_str("\r\n");
+
+      // One write, so that a small response goes out in one packet rather
+      // than waiting on Nagle's algorithm for the head to be acknowledged.
+      let mut bytes = head.into_bytes();
+
+      bytes.extend_from_slice(&self.body);
+
+      w.write_all(&bytes)?;
+      w.flush()
+   }
+}
+
+/// Returns the reason phrase of a status code.
+pub fn reason(status: u16) -> &'static str {
+   match status {
+      200 => "OK",
+      204 => "No Content",
+      301 => "Moved Permanently",
+      304 => "Not Modified",
+      308 => "Permanent Redirect",
+      400 => "Bad Request",
+      403 => "Forbidden",
+      404 => "Not Found",
+      405 => "Method Not Allowed",
+      408 => "Request Timeout",
+      413 => "Payload Too Large",
+      431 => "Request Header Fields Too Large",
+      500 => "Internal Server Error",
+      503 => "Service Unavailable",
+      505 => "HTTP Version Not Supported",
+      _ => "Unknown",
+   }
+}
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Formats a time as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+/// Times before 1970 are formatted as 1970.
+pub fn http_date(time: SystemTime) -> String {
+   let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+   let days = secs / 86400;
+   let (year, month, day) = civil_from_days(days as i64);
+
+   format!(
+      "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+      DAYS[((days + 4) % 7) as usize],
+      day,
+      MONTHS[month as usize - 1],
+      year,
+      secs % 86400 / 3600,
+      secs % 3600 / 60,
+      secs % 60
+   )
+}
+
+/// Parses an HTTP date in the preferred `IMF-fixdate` format. The obsolete
+/// formats are not accepted.
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+   let parts: Vec<&str> = s.split(' ').collect();
+
+   if parts.len() != 6 || !DAYS.iter().any(|day| parts[0] == format!("{},", day)) || parts[5] != "GMT" {
+      return None;
+   }
+
+   let number = |s: &str, len: usize| -> Option<u64> {
+      if s.len() == len && s.bytes().all(|b| b.is_ascii_digit()) {
+         s.parse().ok()
+      } else {
+         None
+      }
+   };
+
+   let day = number(parts[1], 2)?;
+   let month = MONTHS.iter().position(|&month| month == parts[2])? as u64 + 1;
+   let year = number(parts[3], 4)?;
+
+   let time: Vec<&str> = parts[4].split(':').collect();
+
+   if time.len() != 3 {
+      return None;
+   }
+
+   let (hour, minute, second) = (number(time[0], 2)?, number(time[1], 2)?, number(time[2], 2)?);
+
+   if year < 1970 || day == 0 || day > 31 || hour > 23 || minute > 59 || second > 60 {
+      return None;
+   }
+
+   let days = days_from_civil(year as i64, month as i64, day as i64) as u64;
+
+   Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + hour * 3600 + minute * 60 + second))
+}
+
+// Howard Hinnant's algorithms for converting between days since 1970-01-01
+// and proleptic Gregorian dates.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+   let z = days + 719468;
+   let era = z.div_euclid(146097);
+   let doe = z - era * 146097;
+   let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+   let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+   let mp = (5 * doy + 2) / 153;
+   let day = doy - (153 * mp + 2) / 5 + 1;
+   let month = if mp < 10 { mp + 3 } else { mp - 9 };
+
+   (yoe + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+   let year = if month <= 2 { year - 1 } else { year };
+   let era = year.div_euclid(400);
+   let yoe = year - era * 400;
+   let mp = if month > 2 { month - 3 } else { month + 9 };
+   let doy = (153 * mp + 2) / 5 + day - 1;
+   let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+   era * 146097 + doe - 719468
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+   haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Whether `s` is an RFC 9110 token, as methods and header names are.
+fn is_token(s: &str) -> bool {
+   !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn is_http_version(s: &str) -> bool {
+   let bytes = s.as_bytes();
+
+   bytes.len() == 8
+      && s.starts_with("HTTP/")
+      && bytes[5].is_ascii_digit()
+      && bytes[6] == b'.'
+      && bytes[7].is_ascii_digit()
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use std::io::Cursor;
+
+   /** Hands out at most `step` bytes per read, like a slow connection. */
+   struct Trickle {
+      data: Vec<u8>,
+      pos: usize,
+      step: usize,
+   }
+
+   impl Read for Trickle {
+      fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+         let len = self.step.min(buf.len()).min(self.data.len() - self.pos);
+
+         buf[..len].copy_from_slice(&self.data[self.pos..self.pos + len]);
+         self.pos += len;
+
+         Ok(len)
+      }
+   }
+
+   fn read_one(data: &[u8]) -> Result<Option<Request>, ParseError> {
+      RequestReader::new(Cursor::new(data.to_vec())).read_request()
+   }
+
+   #[test]
+   fn simple_get() {
+      let request = read_one(b"GET /sleep?x=1 HTTP/1.1\r\nHost: localhost\r\nAccept:  */* \r\n\r\n")
+         .unwrap()
+         .unwrap();
+
+      assert_eq!("GET", request.method);
+      assert_eq!("/sleep?x=1", request.target);
+      assert_eq!("/sleep", request.path());
+      assert_eq!(Version::Http11, request.version);
+      assert_eq!(Some("localhost"), request.header("host"));
+      assert_eq!(Some("*/*"), request.header("ACCEPT"));
+      assert!(request.body.is_empty());
+   }
+
+   #[test]
+   fn request_spanning_many_reads() {
+      let data = b"POST /form HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world".to_vec();
+      let mut reader = RequestReader::new(Trickle { data, pos: 0, step: 3 });
+
+      let request = reader.read_request().unwrap().unwrap();
+
+      assert_eq!("POST", request.method);
+      assert_eq!(b"hello world".to_vec(), request.body);
+      assert!(reader.read_request().unwrap().is_none());
+   }
+
+   #[test]
+   fn chunked_body_with_trailer() {
+      let data = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+         5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nChecksum: abc\r\n\r\n".to_vec();
+      let mut reader = RequestReader::new(Trickle { data, pos: 0, step: 7 });
+
+      let request = reader.read_request().unwrap().unwrap();
+
+      assert_eq!(b"hello world".to_vec(), request.body);
+      assert_eq!(vec![("Checksum".to_string(), "abc".to_string())], request.trailers);
+      assert_eq!(None, request.header("checksum"));
+   }
+
+   #[test]
+   fn huge_chunk_sizes_are_too_large() {
+      let sizes = ["ffffffffffffffff", "fffffffffffffffe", &format!("{:x}", MAX_BODY)];
+
+      for size in &sizes {
+         let data = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n{}\r\nx\r\n", size);
+
+         assert!(matches!(read_one(data.as_bytes()), Err(ParseError::BodyTooLarge)), "{}", size);
+      }
+   }
+
+   #[test]
+   fn back_to_back_requests() {
+      let data = b"GET /a HTTP/1.1\r\n\r\nPOST /b HTTP/1.0\r\nContent-Length: 2\r\n\r\nhiGET /c HTTP/1.1\r\n\r\n";
+      let mut reader = RequestReader::new(Cursor::new(data.to_vec()));
+
+      let targets: Vec<String> = (0..3)
+         .map(|_| reader.read_request().unwrap().unwrap().target)
+         .collect();
+
+      assert_eq!(vec!["/a", "/b", "/c"], targets);
+      assert!(reader.read_request().unwrap().is_none());
+   }
+
+   #[test]
+   fn malformed_requests() {
+      let cases: [&[u8]; 8] = [
+         b"GET /\r\n\r\n",
+         b"GET  / HTTP/1.1\r\n\r\n",
+         b"G(T / HTTP/1.1\r\n\r\n",
+         b"GET / HTTP/1.1\r\nNo colon\r\n\r\n",
+         b"GET / HTTP/1.1\r\nBad name: x\r\n\r\n",
+         b"GET / HTTP/1.1\r\nHost: a\r\n folded\r\n\r\n",
+         b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+         b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+      ];
+
+      for case in &cases {
+         match read_one(case) {
+            Err(e @ ParseError::Malformed(_)) => assert_eq!(400, e.response().unwrap().status),
+            other => panic!("{:?} parsed as {:?}", String::from_utf8_lossy(case), other),
+         }
+      }
+   }
+
+   #[test]
+   fn limits_and_versions() {
+      let mut huge = b"GET / HTTP/1.1\r\n".to_vec();
+      huge.extend(std::iter::repeat_n(b'a', MAX_HEAD));
+
+      assert!(matches!(read_one(&huge), Err(ParseError::HeadTooLarge)));
+
+      let body = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+
+      assert!(matches!(read_one(body.as_bytes()), Err(ParseError::BodyTooLarge)));
+      assert!(matches!(read_one(b"GET / HTTP/2.0\r\n\r\n"), Err(ParseError::UnsupportedVersion)));
+      assert!(matches!(read_one(b"GET / HTTP/1.1\r\nHost"), Err(ParseError::UnexpectedEof)));
+      assert!(read_one(b"").unwrap().is_none());
+   }
+
+   #[test]
+   fn http_dates() {
+      let time = UNIX_EPOCH + Duration::from_secs(784111777);
+
+      assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", http_date(time));
+      assert_eq!("Thu, 01 Jan 1970 00:00:00 GMT", http_date(UNIX_EPOCH));
+      assert_eq!("Tue, 29 Feb 2028 23:59:59 GMT", http_date(parse_http_date("Tue, 29 Feb 2028 23:59:59 GMT").unwrap()));
+      assert_eq!(Some(time), parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"));
+      assert_eq!(None, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+      assert_eq!(None, parse_http_date("Sun, 6 Nov 1994 08:49:37 GMT"));
+   }
+
+   #[test]
+   fn writes_response() {
+      let mut out = Vec::new();
+
+      Response::new(404).header("Content-Type", "text/html").body("nope").write_to(&mut out).unwrap();
+
+      assert_eq!(
+         "HTTP/1.1 404 Not Found\r\nContent-Type: text/html\r\nContent-Length: 4\r\n\r\nnope",
+         String::from_utf8(out).unwrap()
+      );
+
+      let mut out = Vec::new();
+
+      Response::new(304).header("ETag", "\"1\"").write_to(&mut out).unwrap();
+
+      assert_eq!("HTTP/1.1 304 Not Modified\r\nETag: \"1\"\r\n\r\n", String::from_utf8(out).unwrap());
+   }
+}
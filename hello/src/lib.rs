@@ -1,127 +1,22 @@
-/* System includes */
-use std::thread;
-
-use std::sync::mpsc;
-use std::sync::Arc;
-use std::sync::Mutex;
-
-/** Holds a pool of threads for execution. */
-pub struct ThreadPool {
-   workers: Vec<Worker>,
-   sender: mpsc::Sender<Message>,
-}
-
-trait FnBox {
-   fn call_box(self: Box<Self>);
-}
-
-impl<F: FnOnce()> FnBox for F {
-   fn call_box(self: Box<F>) {
-      (*self)()
-   }
-}
-
-type Job = Box<dyn FnBox + Send + 'static>;
-
-enum Message {
-   NewJob(Job),
-   Terminate,
-}
-
-impl ThreadPool {
-   /// Create a new ThreadPool.
-   /// 
-   /// ## Input:
-   /// 
-   /// * `size` - The number of threads in the pool.
-   /// 
-   /// ## Panics
-   /// 
-   /// * The 'new' function will panic if the size is zero.
-   pub fn new(size: usize) -> ThreadPool {
-      assert!(size > 0);
-
-      let (sender, receiver) = mpsc::channel();
-
-      let receiver = Arc::new(Mutex::new(receiver));
-
-      let mut workers = Vec::with_capacity(size);
-
-      for id in 0..size {
-         workers.push(Worker::new(id, Arc::clone(&receiver)));
-      }
-
-      ThreadPool {
-         workers,
-         sender,
-      }
-   }
-
-   pub fn execute<F>(&self, f: F)
-      where
-         F: FnOnce() + Send + 'static
-   {
-      let job = Box::new(f);
-
-      self.sender.send(Message::NewJob(job)).unwrap();
-   }
-}
-
-impl Drop for ThreadPool {
-   fn drop(&mut self) {
-      println!("Sending terminate message to all workers.");
-
-      for _ in &mut self.workers {
-         self.sender.send(Message::Terminate).unwrap();
-      }
-
-      println!("Shutting down all workers.");
-
-      for worker in &mut self.workers {
-         println!("Shutting down worker {}", worker.id);
-
-         if let Some(thread) = worker.thread.take() {
-            thread.join().unwrap();
-         }
-      }
-   }
-}
-
-/** Keeps track of a single thread. */
-struct Worker {
-   id: usize,
-   thread: Option<thread::JoinHandle<()>>,
-}
-
-impl Worker {
-   /// Create a new Worker.
-   /// 
-   /// ## Input:
-   /// 
-   /// * `id` - The ID of the new worker.
-   fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
-      let thread = thread::spawn(move || {
-         loop {
-            let message = receiver.lock().unwrap().recv().unwrap();
-
-            match message {
-               Message::NewJob(job) => {
-                  println!("Worker {} got a job; executing.", id);
-
-                  job.call_box();
-               },
-               Message::Terminate => {
-                  println!("Worker {} was told to terminate.", id);
-
-                  break;
-               }
-            }
-         }
-      });
-
-      Worker {
-         id,
-         thread: Some(thread),
-      }
-   }
-}
\ No newline at end of file
+/* extern crate definitions */
+#[macro_use]
+extern crate log;
+extern crate mio;
+extern crate rustls;
+#[cfg(test)]
+extern crate tempfile;
+
+/* Modules */
+pub mod http;
+pub mod metrics;
+pub mod pool;
+pub mod reactor;
+pub mod router;
+pub mod server;
+pub mod static_files;
+#[cfg(test)]
+mod test_support;
+pub mod tls;
+
+/* Re-exports */
+pub use pool::{panic_message, JobHandle, Metrics, Priority, Rejected, RejectionPolicy, Scope, ScopedJobHandle, Spawner, ThreadPool, TimerHandle, WorkerMetrics};
\ No newline at end of file
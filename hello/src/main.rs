@@ -1,55 +1,214 @@
 /* extern crate definitions */
+extern crate env_logger;
 extern crate hello;
+#[macro_use]
+extern crate log;
+extern crate signal_hook;
 
 /* extern create usings */
-use hello::ThreadPool;
+use hello::http::Response;
+use hello::reactor;
+use hello::router::Router;
+use hello::server::{Server, ServerOptions, Shutdown};
+use hello::static_files::StaticFiles;
+use hello::tls;
+
+use env_logger::Env;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
 
 /* system includes */
+use std::env;
 use std::fs;
-
-use std::io::prelude::*;
+use std::process;
 
 use std::net::TcpListener;
-use std::net::TcpStream;
 
 use std::thread;
 use std::time::Duration;
 
 fn main() {
+   // RUST_LOG=debug (or trace, for every job) shows what the pool is doing.
+   env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+   let args = Args::parse(env::args().skip(1)).unwrap_or_else(|err| {
+      eprintln!("Problem passing arguments: {}", err);
+      eprintln!("{}", USAGE);
+      process::exit(1);
+   });
+
+   let options = ServerOptions {
+      stats_interval: Some(Duration::from_secs(60)),
+      ..ServerOptions::default()
+   };
+
+   // With a certificate, HTTPS runs alongside plain HTTP on its own port.
+   let https = args.cert.as_ref().zip(args.key.as_ref()).map(|(cert, key)| {
+      let config = tls::load_config(cert, key).unwrap_or_else(|e| panic!("Cannot load {}: {}", cert, e));
+      let listener = TcpListener::bind(("127.0.0.1", args.https_port)).unwrap();
+
+      Server::new(listener, router(args.root.clone()), ServerOptions {
+         tls: Some(config),
+         ..options.clone()
+      })
+   });
+
+   let plain = if args.redirect && https.is_some() {
+      tls::redirect(args.https_port)
+   } else {
+      router(args.root.clone())
+   };
+
    let listener = TcpListener::bind("127.0.0.1:8080").unwrap();
-   let pool = ThreadPool::new(4);
+   let mut shutdowns = Vec::new();
 
-   for stream in listener.incoming().take(2) {
-      let stream = stream.unwrap();
+   let https = https.map(|server| {
+      shutdowns.push(server.shutdown_handle().unwrap());
 
-      pool.execute(|| {
-         handle_connection(stream);
+      thread::spawn(move || server.run())
+   });
+
+   let summary = if args.reactor {
+      let server = reactor::Server::new(listener, plain, options).unwrap();
+
+      shutdowns.push(server.shutdown_handle().unwrap());
+      stop_on_signal(shutdowns);
+      server.run()
+   } else {
+      let server = Server::new(listener, plain, options);
+
+      shutdowns.push(server.shutdown_handle().unwrap());
+      stop_on_signal(shutdowns);
+      server.run()
+   };
+
+   info!("HTTP: {}", summary);
+
+   if let Some(https) = https {
+      info!("HTTPS: {}", https.join().unwrap());
+   }
+}
+
+/// What `hello` takes, shown when its arguments are wrong.
+const USAGE: &str = "Usage: hello [--reactor] [--cert PEM --key PEM [--https-port PORT] [--redirect]] [DIR]";
+
+/** The command line, as `USAGE` shows it. */
+struct Args {
+   /// Serve plain HTTP from one event loop rather than a thread per
+   /// connection.
+   reactor: bool,
+   cert: Option<String>,
+   key: Option<String>,
+   https_port: u16,
+   /// Answer plain HTTP with redirects to HTTPS.
+   redirect: bool,
+   root: Option<String>,
+}
+
+impl Args {
+   fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+      let mut parsed = Args {
+         reactor: false,
+         cert: None,
+         key: None,
+         https_port: 8443,
+         redirect: false,
+         root: None,
+      };
+
+      while let Some(arg) = args.next() {
+         let mut value = || args.next().ok_or_else(|| format!("Option {} requires a value.", arg));
+
+         match arg.as_str() {
+            "--reactor" => parsed.reactor = true,
+            "--redirect" => parsed.redirect = true,
+            "--cert" => parsed.cert = Some(value()?),
+            "--key" => parsed.key = Some(value()?),
+            "--https-port" => match value()?.parse() {
+               Ok(port) => parsed.https_port = port,
+               Err(_) => return Err("Option --https-port requires a port number.".to_string()),
+            },
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}.", arg)),
+            _ => parsed.root = Some(arg),
+         }
+      }
+
+      if parsed.cert.is_some() != parsed.key.is_some() {
+         return Err("Options --cert and --key go together.".to_string());
+      }
+
+      Ok(parsed)
+   }
+}
+
+fn stop_on_signal(shutdowns: Vec<Shutdown>) {
+   let mut signals = Signals::new([SIGINT, SIGTERM]).unwrap();
+
+   thread::spawn(move || {
+      if let Some(signal) = signals.forever().next() {
+         info!("Received signal {}; shutting down.", signal);
+
+         for shutdown in shutdowns {
+            shutdown.trigger();
+         }
+      }
+   });
+}
+
+fn router(root: Option<String>) -> Router {
+   let mut router = Router::new();
+
+   router
+      .get("/", |_, _| html(200, "hello.html"))
+      .get("/sleep", |_, _| html(200, "hello.html").delay(Duration::from_secs(5)))
+      .not_found(|_, _| html(404, "404.html"));
+
+   // `hello DIR` also serves the files in DIR under /static/.
+   if let Some(root) = root {
+      let files = StaticFiles::new(&root).unwrap_or_else(|e| panic!("Cannot serve {}: {}", root, e));
+
+      router.get("/static/*path", move |request, params| {
+         files.serve(request, params.get("path").unwrap_or(""))
       });
    }
 
-   println!("Shutting down.");
+   router
 }
 
-fn handle_connection(mut stream: TcpStream) {
-   let mut buffer = [0; 512];
-   stream.read(&mut buffer).unwrap();
+fn html(status: u16, filename: &str) -> Response {
+   match fs::read(filename) {
+      Ok(contents) => Response::new(status)
+         .header("Content-Type", "text/html; charset=utf-8")
+         .body(contents),
+      Err(_) => Response::new(500),
+   }
+}
 
-   let get = b"GET / HTTP/1.1\r\n";
-   let sleep = b"GET /sleep HTTP/1.1\r\n";
+#[cfg(test)]
+mod tests {
+   use super::*;
 
-   let (status_line, filename) = if buffer.starts_with(get) {
-      ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
-   } else if buffer.starts_with(sleep) {
-      thread::sleep(Duration::from_secs(5));
-      ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
-   } else {
-      ("HTTP/1.1 404 NOT FOUND\r\n\r\n", "404.html")
-   };
+   fn parse(args: &[&str]) -> Result<Args, String> {
+      Args::parse(args.iter().map(|arg| arg.to_string()))
+   }
 
-   let contents = fs::read_to_string(filename).unwrap();
+   #[test]
+   fn parses_options() {
+      let args = parse(&["--reactor", "--cert", "cert.pem", "--key", "key.pem", "--https-port", "9443", "site"]).unwrap();
 
-   let response = format!("{}{}", status_line, contents);
+      assert!(args.reactor);
+      assert_eq!(Some("cert.pem".to_string()), args.cert);
+      assert_eq!(Some("key.pem".to_string()), args.key);
+      assert_eq!(9443, args.https_port);
+      assert_eq!(Some("site".to_string()), args.root);
+   }
 
-   stream.write(response.as_bytes()).unwrap();
-   stream.flush().unwrap();
-}
\ No newline at end of file
+   #[test]
+   fn reports_bad_arguments() {
+      assert_eq!(Err("Option --https-port requires a port number.".to_string()), parse(&["--https-port", "http"]).map(|_| ()));
+      assert_eq!(Err("Option --key requires a value.".to_string()), parse(&["--cert", "cert.pem", "--key"]).map(|_| ()));
+      assert_eq!(Err("Options --cert and --key go together.".to_string()), parse(&["--cert", "cert.pem"]).map(|_| ()));
+      assert_eq!(Err("Unknown option --verbose.".to_string()), parse(&["--verbose"]).map(|_| ()));
+   }
+}
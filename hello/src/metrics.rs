@@ -0,0 +1,183 @@
+/* System includes */
+use std::fmt;
+
+use std::convert::TryFrom;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use std::time::Duration;
+
+/// Bucket 0 counts durations under a microsecond, bucket `i` those under
+/// 2^`i` microseconds, and the last bucket everything longer.
+const BUCKETS: usize = 32;
+
+/** Counts durations in buckets of doubling width. Many threads may record
+ * at once without locking.
+ */
+pub struct Recorder {
+   buckets: [AtomicU64; BUCKETS],
+   count: AtomicU64,
+   /// The sum of every duration recorded, in nanoseconds.
+   total: AtomicU64,
+}
+
+/** The durations a Recorder has counted, as of `Recorder::snapshot`. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+   buckets: Vec<u64>,
+   count: u64,
+   total: Duration,
+}
+
+impl Recorder {
+   pub fn new() -> Recorder {
+      Recorder {
+         buckets: Default::default(),
+         count: AtomicU64::new(0),
+         total: AtomicU64::new(0),
+      }
+   }
+
+   pub fn record(&self, duration: Duration) {
+      let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+
+      self.buckets[bucket(duration)].fetch_add(1, Ordering::Relaxed);
+      self.count.fetch_add(1, Ordering::Relaxed);
+      self.total.fetch_add(nanos, Ordering::Relaxed);
+   }
+
+   /// Returns the counts so far. Durations recorded while the snapshot is
+   /// taken may be missing from some of its totals.
+   pub fn snapshot(&self) -> Histogram {
+      Histogram {
+         buckets: self.buckets.iter().map(|bucket| bucket.load(Ordering::Relaxed)).collect(),
+         count: self.count.load(Ordering::Relaxed),
+         total: Duration::from_nanos(self.total.load(Ordering::Relaxed)),
+      }
+   }
+}
+
+impl Default for Recorder {
+   fn default() -> Recorder {
+      Recorder::new()
+   }
+}
+
+impl fmt::Debug for Recorder {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      f.debug_tuple("Recorder").field(&self.snapshot()).finish()
+   }
+}
+
+impl Histogram {
+   /// Returns the number of durations counted.
+   pub fn count(&self) -> u64 {
+      self.count
+   }
+
+   /// Returns the mean duration, or None if nothing was counted.
+   pub fn mean(&self) -> Option<Duration> {
+      if self.count == 0 {
+         return None;
+      }
+
+      let mean = self.total.as_nanos() / u128::from(self.count);
+
+      Some(Duration::from_nanos(mean as u64))
+   }
+
+   /// Returns a duration that `percent` percent of those counted were no
+   /// longer than: the upper bound of the bucket the percentile falls in.
+   ///
+   /// ## Input:
+   ///
+   /// * `percent` - Between 0 and 100.
+   ///
+   /// ## Returns
+   ///
+   /// * `None` - If nothing was counted.
+   pub fn percentile(&self, percent: f64) -> Option<Duration> {
+      if self.count == 0 {
+         return None;
+      }
+
+      let rank = ((percent / 100.0 * self.count as f64).ceil() as u64).max(1);
+      let mut seen = 0;
+
+      self.buckets.iter().position(|&count| {
+         seen += count;
+
+         seen >= rank
+      }).or(Some(BUCKETS - 1)).map(upper_bound)
+   }
+
+   /// Returns the upper bound and count of every bucket, shortest first.
+   pub fn buckets(&self) -> Vec<(Duration, u64)> {
+      self.buckets.iter().enumerate().map(|(i, &count)| (upper_bound(i), count)).collect()
+   }
+}
+
+impl fmt::Display for Histogram {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match (self.mean(), self.percentile(50.0), self.percentile(99.0)) {
+         (Some(mean), Some(p50), Some(p99)) => {
+            write!(f, "n={} mean={:?} p50<={:?} p99<={:?}", self.count, mean, p50, p99)
+         },
+         _ => write!(f, "n=0"),
+      }
+   }
+}
+
+fn bucket(duration: Duration) -> usize {
+   let micros = duration.as_micros();
+
+   if micros == 0 {
+      0
+   } else {
+      ((128 - micros.leading_zeros()) as usize).min(BUCKETS - 1)
+   }
+}
+
+/// Returns the duration bucket `i` counts up to. The last bucket has no
+/// bound, so this is where it starts instead.
+fn upper_bound(i: usize) -> Duration {
+   Duration::from_micros(1 << i.min(BUCKETS - 2))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn buckets_double_in_width() {
+      assert_eq!(0, bucket(Duration::from_nanos(999)));
+      assert_eq!(1, bucket(Duration::from_micros(1)));
+      assert_eq!(2, bucket(Duration::from_micros(2)));
+      assert_eq!(2, bucket(Duration::from_micros(3)));
+      assert_eq!(10, bucket(Duration::from_millis(1)));
+      assert_eq!(BUCKETS - 1, bucket(Duration::from_secs(86400)));
+   }
+
+   #[test]
+   fn summarises_what_was_recorded() {
+      let recorder = Recorder::new();
+
+      assert_eq!(None, recorder.snapshot().mean());
+      assert_eq!(None, recorder.snapshot().percentile(50.0));
+
+      for _ in 0..99 {
+         recorder.record(Duration::from_micros(10));
+      }
+
+      recorder.record(Duration::from_millis(10));
+
+      let histogram = recorder.snapshot();
+
+      assert_eq!(100, histogram.count());
+      assert_eq!(Some(Duration::from_nanos(109_900)), histogram.mean());
+      assert_eq!(Some(Duration::from_micros(16)), histogram.percentile(50.0));
+      assert_eq!(Some(Duration::from_micros(16)), histogram.percentile(99.0));
+      assert_eq!(Some(Duration::from_micros(16384)), histogram.percentile(100.0));
+      assert_eq!(100, histogram.buckets().iter().map(|&(_, count)| count).sum::<u64>());
+   }
+}
@@ -0,0 +1,158 @@
+/* System includes */
+use std::thread;
+
+use std::collections::VecDeque;
+
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::{Arc, Condvar, Mutex};
+
+use std::time::Duration;
+
+/* extern create usings */
+use metrics::Recorder;
+use super::{RejectionPolicy, Shard, Shared, Slot, ThreadPool, Timers, WorkerStats};
+
+/** Configures a ThreadPool. */
+#[derive(Debug, Clone)]
+pub struct Builder {
+   pub(super) min_threads: usize,
+   pub(super) max_threads: usize,
+   pub(super) keep_alive: Duration,
+   pub(super) thread_name: Option<String>,
+   pub(super) stack_size: Option<usize>,
+   pub(super) queue_capacity: Option<usize>,
+   pub(super) rejection_policy: RejectionPolicy,
+   pub(super) aging: Duration,
+}
+
+impl Builder {
+   /// Create a new Builder for a pool of one thread per CPU.
+   pub fn new() -> Builder {
+      let cpus = thread::available_parallelism().map(|cpus| cpus.get()).unwrap_or(1);
+
+      Builder {
+         min_threads: cpus,
+         max_threads: cpus,
+         keep_alive: Duration::from_secs(60),
+         thread_name: None,
+         stack_size: None,
+         queue_capacity: None,
+         rejection_policy: RejectionPolicy::Block,
+         aging: Duration::from_secs(1),
+      }
+   }
+
+   /// The number of workers kept even when idle.
+   pub fn min_threads(mut self, min_threads: usize) -> Builder {
+      self.min_threads = min_threads;
+
+      self
+   }
+
+   /// The most workers the pool will run at once.
+   pub fn max_threads(mut self, max_threads: usize) -> Builder {
+      self.max_threads = max_threads;
+
+      self
+   }
+
+   /// How long a worker beyond `min_threads` waits for a job before it
+   /// stops.
+   pub fn keep_alive(mut self, keep_alive: Duration) -> Builder {
+      self.keep_alive = keep_alive;
+
+      self
+   }
+
+   /// Names the worker threads `<name>-<slot>`.
+   pub fn thread_name(mut self, name: &str) -> Builder {
+      self.thread_name = Some(name.to_string());
+
+      self
+   }
+
+   /// The stack size of the worker threads, in bytes.
+   pub fn stack_size(mut self, stack_size: usize) -> Builder {
+      self.stack_size = Some(stack_size);
+
+      self
+   }
+
+   /// The most jobs to queue before applying the rejection policy. The
+   /// queue is unbounded by default.
+   pub fn queue_capacity(mut self, queue_capacity: usize) -> Builder {
+      self.queue_capacity = Some(queue_capacity);
+
+      self
+   }
+
+   /// What to do with jobs submitted when the queue is full.
+   pub fn rejection_policy(mut self, rejection_policy: RejectionPolicy) -> Builder {
+      self.rejection_policy = rejection_policy;
+
+      self
+   }
+
+   /// How long a queued job waits before it counts as one priority higher.
+   /// Only jobs queued from outside the pool age; see `ThreadPool`.
+   pub fn aging(mut self, aging: Duration) -> Builder {
+      self.aging = aging;
+
+      self
+   }
+
+   /// Create the ThreadPool and start its `min_threads` workers.
+   ///
+   /// ## Panics
+   ///
+   /// * The 'build' function will panic if `max_threads` is zero or less
+   ///   than `min_threads`, if `queue_capacity` or `aging` is zero, or if a
+   ///   worker thread cannot be started.
+   pub fn build(self) -> ThreadPool {
+      assert!(self.max_threads > 0);
+      assert!(self.min_threads <= self.max_threads);
+      assert!(self.queue_capacity != Some(0));
+      assert!(self.aging > Duration::from_secs(0));
+
+      let shared = Arc::new(Shared {
+         injector: (0..self.max_threads).map(|_| Shard::default()).collect(),
+         urgent: AtomicUsize::new(0),
+         locals: (0..self.max_threads).map(|_| Mutex::new(VecDeque::new())).collect(),
+         stats: (0..self.max_threads).map(|_| WorkerStats::default()).collect(),
+         slots: Mutex::new((0..self.max_threads).map(|_| Slot::default()).collect()),
+         pending: AtomicUsize::new(0),
+         live: AtomicUsize::new(0),
+         idle: AtomicUsize::new(0),
+         completed: AtomicUsize::new(0),
+         wait_time: Recorder::new(),
+         run_time: Recorder::new(),
+         rejected: AtomicUsize::new(0),
+         blocked: AtomicUsize::new(0),
+         room: Mutex::new(()),
+         room_freed: Condvar::new(),
+         sleepers: AtomicUsize::new(0),
+         sleep: Mutex::new(()),
+         wake: Condvar::new(),
+         timers: Mutex::new(Timers::default()),
+         timer_wake: Condvar::new(),
+         shutdown: AtomicBool::new(false),
+         config: self,
+      });
+
+      for _ in 0..shared.config.min_threads {
+         if let Err(e) = shared.grow() {
+            panic!("Failed to start a worker: {}", e);
+         }
+      }
+
+      ThreadPool {
+         shared,
+      }
+   }
+}
+
+impl Default for Builder {
+   fn default() -> Builder {
+      Builder::new()
+   }
+}
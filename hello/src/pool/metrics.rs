@@ -0,0 +1,155 @@
+/* System includes */
+use std::fmt;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use std::time::{Duration, Instant};
+
+/* extern create usings */
+use metrics::Histogram;
+use super::{lock, Shared};
+
+#[derive(Default)]
+pub(super) struct WorkerStats {
+   pub(super) started: Mutex<Option<Instant>>,
+   pub(super) jobs: AtomicU64,
+   /// Time spent running jobs, in nanoseconds.
+   pub(super) busy: AtomicU64,
+}
+
+/** What a ThreadPool is doing, as of `ThreadPool::metrics`. */
+#[derive(Debug, Clone)]
+pub struct Metrics {
+   /// Jobs waiting for a worker.
+   pub queued: usize,
+   /// Workers running a job.
+   pub active: usize,
+   pub threads: usize,
+   pub completed: usize,
+   pub rejected: usize,
+   /// How long jobs waited for a worker.
+   pub wait_time: Histogram,
+   /// How long jobs ran, including those that panicked.
+   pub run_time: Histogram,
+   /// The workers running now, by slot.
+   pub workers: Vec<WorkerMetrics>,
+}
+
+/** What one worker has done since it started. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerMetrics {
+   /// The worker's slot, as in its thread name.
+   pub id: usize,
+   pub jobs: u64,
+   /// Time spent running jobs.
+   pub busy: Duration,
+   pub uptime: Duration,
+}
+
+impl WorkerMetrics {
+   /// Returns the share of its uptime the worker spent running jobs.
+   pub fn utilization(&self) -> f64 {
+      if self.uptime == Duration::from_secs(0) {
+         return 0.0;
+      }
+
+      (self.busy.as_secs_f64() / self.uptime.as_secs_f64()).min(1.0)
+   }
+}
+
+impl fmt::Display for Metrics {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(
+         f,
+         "{} queued, {}/{} threads busy, {} completed, {} rejected; waited {}; ran {}; utilization",
+         self.queued,
+         self.active,
+         self.threads,
+         self.completed,
+         self.rejected,
+         self.wait_time,
+         self.run_time
+      )?;
+
+      for worker in &self.workers {
+         write!(f, " {}:{:.0}%", worker.id, worker.utilization() * 100.0)?;
+      }
+
+      Ok(())
+   }
+}
+
+impl Shared {
+   pub(super) fn metrics(&self) -> Metrics {
+      let now = Instant::now();
+      let workers = lock(&self.slots)
+         .iter()
+         .enumerate()
+         .filter(|&(_, slot)| slot.live)
+         .filter_map(|(id, _)| {
+            let stats = &self.stats[id];
+            let started = (*lock(&stats.started))?;
+
+            Some(WorkerMetrics {
+               id,
+               jobs: stats.jobs.load(Ordering::Relaxed),
+               busy: Duration::from_nanos(stats.busy.load(Ordering::Relaxed)),
+               uptime: now.saturating_duration_since(started),
+            })
+         })
+         .collect();
+
+      let live = self.live.load(Ordering::SeqCst);
+
+      Metrics {
+         queued: self.pending.load(Ordering::SeqCst),
+         active: live.saturating_sub(self.idle.load(Ordering::SeqCst)),
+         threads: live,
+         completed: self.completed.load(Ordering::SeqCst),
+         rejected: self.rejected.load(Ordering::SeqCst),
+         wait_time: self.wait_time.snapshot(),
+         run_time: self.run_time.snapshot(),
+         workers,
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use pool::tests::{blocked_pool, eventually};
+
+   use std::thread;
+
+   #[test]
+   fn metrics_snapshot() {
+      let (pool, release) = blocked_pool(Duration::from_secs(60));
+
+      for _ in 0..4 {
+         pool.execute(|| thread::sleep(Duration::from_millis(5)));
+      }
+
+      let busy = pool.metrics();
+
+      assert_eq!(4, busy.queued);
+      assert_eq!((1, 1), (busy.active, busy.threads));
+      assert_eq!(1, busy.wait_time.count());
+      assert_eq!(0, busy.run_time.count());
+
+      release.send(()).unwrap();
+      eventually(|| pool.completed_count() == 5);
+
+      let done = pool.metrics();
+      let worker = &done.workers[0];
+
+      assert_eq!(0, done.queued);
+      assert_eq!(5, done.wait_time.count());
+      assert_eq!(5, done.run_time.count());
+      assert!(done.run_time.percentile(50.0).unwrap() >= Duration::from_millis(5));
+      assert_eq!((0, 5), (worker.id, worker.jobs));
+      assert!(worker.busy >= Duration::from_millis(20));
+      assert!(worker.utilization() > 0.0 && worker.utilization() <= 1.0);
+   }
+}
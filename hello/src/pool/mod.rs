@@ -0,0 +1,1058 @@
+/* System includes */
+use std::fmt;
+use std::io;
+use std::ptr;
+use std::thread;
+
+use std::any::Any;
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+use std::time::{Duration, Instant};
+
+/* extern create usings */
+use metrics::Recorder;
+
+/* Modules */
+mod builder;
+mod metrics;
+mod queue;
+mod scope;
+mod timer;
+
+/* Re-exports */
+pub use self::builder::Builder;
+pub use self::metrics::{Metrics, WorkerMetrics};
+pub use self::queue::{Priority, Rejected, RejectionPolicy};
+pub use self::scope::{Scope, ScopedJobHandle};
+pub use self::timer::TimerHandle;
+
+use self::metrics::WorkerStats;
+use self::queue::{execute, submit, Shard};
+use self::scope::scope;
+use self::timer::{execute_after, execute_every, Timers};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A job and when it was queued.
+type Queued = (Instant, Job);
+
+/// How many times an idle worker looks for a job before going to sleep.
+const SPIN_ROUNDS: usize = 16;
+
+/** Holds a pool of threads for execution.
+ *
+ * Every worker has its own deque of jobs. Jobs submitted from outside the
+ * pool go to a shared injector queue, split into shards so that threads
+ * submitting at once seldom wait on each other; jobs submitted by a job
+ * running on a worker go to that worker's deque, which it works through
+ * newest first.
+ * A worker with nothing to do takes the oldest job from the injector, then
+ * steals the oldest job from another worker's deque, and only then sleeps.
+ *
+ * The injector has a lane for each `Priority`, and a worker takes from the
+ * highest lane first. So that a busy pool does not starve the lower lanes,
+ * a queued job counts as one priority higher for every `aging` it has
+ * waited. Only `Normal` jobs go to a worker's own deque; jobs of `High`
+ * priority are taken before it.
+ *
+ * Aging applies to the injector only. Jobs in a worker's own deque are not
+ * aged, and a worker runs them before any injected job below `High`, so
+ * while a job keeps submitting sub-jobs, its worker leaves older `Normal`
+ * and `Low` jobs in the injector to the other workers. Once the deque is
+ * empty, those jobs compete by aged priority as usual.
+ *
+ * The pool starts `min_threads` workers, starts more, up to `max_threads`,
+ * whenever jobs are queued with no idle worker to take them, and stops
+ * workers beyond `min_threads` that have been idle for `keep_alive`.
+ *
+ * With a `queue_capacity`, jobs submitted from outside the pool while that
+ * many are already queued are handled by the `RejectionPolicy`. Jobs that
+ * workers submit are always queued, so a job cannot block on its own pool.
+ *
+ * Jobs scheduled with `execute_after` and `execute_every` wait in a heap
+ * kept by a single timer thread, started with the first of them, which
+ * submits each job to the pool when it is due.
+ */
+pub struct ThreadPool {
+   shared: Arc<Shared>,
+}
+
+/** Submits jobs to a ThreadPool from anywhere, including its own jobs. */
+#[derive(Clone)]
+pub struct Spawner {
+   shared: Arc<Shared>,
+}
+
+struct Shared {
+   config: Builder,
+   /// The injector, one shard per worker slot. Each submitting thread
+   /// keeps to one shard, so that threads submitting at once seldom wait
+   /// on the same lock.
+   injector: Vec<Shard>,
+   /// Jobs in the injector's `High` lane, which workers take before their
+   /// own.
+   urgent: AtomicUsize,
+   /// One deque per worker slot, `max_threads` of them.
+   locals: Vec<Mutex<VecDeque<Queued>>>,
+   /// What the worker in each slot has done since it started.
+   stats: Vec<WorkerStats>,
+   /// Which slots have a running worker, and the threads to join.
+   slots: Mutex<Vec<Slot>>,
+   /// Jobs in any queue, not yet taken by a worker.
+   pending: AtomicUsize,
+   /// Workers started and not yet stopped.
+   live: AtomicUsize,
+   /// Live workers not running a job.
+   idle: AtomicUsize,
+   completed: AtomicUsize,
+   /// How long jobs waited in a queue, and how long they ran.
+   wait_time: Recorder,
+   run_time: Recorder,
+   /// Jobs turned away or dropped because the queue was full.
+   rejected: AtomicUsize,
+   /// Submitters waiting on `room_freed`.
+   blocked: AtomicUsize,
+   room: Mutex<()>,
+   room_freed: Condvar,
+   /// Workers waiting on `wake`.
+   sleepers: AtomicUsize,
+   sleep: Mutex<()>,
+   wake: Condvar,
+   timers: Mutex<Timers>,
+   /// Wakes the timer thread for an earlier timer, or to stop.
+   timer_wake: Condvar,
+   shutdown: AtomicBool,
+}
+
+#[derive(Default)]
+struct Slot {
+   live: bool,
+   thread: Option<thread::JoinHandle<()>>,
+}
+
+thread_local! {
+   /// The pool and the index of the worker the current thread is, if any.
+   static CURRENT: Cell<Option<(*const Shared, usize)>> = const { Cell::new(None) };
+}
+
+impl ThreadPool {
+   /// Create a new ThreadPool.
+   ///
+   /// ## Input:
+   ///
+   /// * `size` - The number of threads in the pool.
+   ///
+   /// ## Panics
+   ///
+   /// * The 'new' function will panic if the size is zero.
+   pub fn new(size: usize) -> ThreadPool {
+      assert!(size > 0);
+
+      Builder::new().min_threads(size).max_threads(size).build()
+   }
+
+   /// Returns a Builder, for pools that grow and shrink.
+   pub fn builder() -> Builder {
+      Builder::new()
+   }
+
+   pub fn execute<F>(&self, f: F)
+      where
+         F: FnOnce() + Send + 'static
+   {
+      execute(&self.shared, Priority::Normal, f);
+   }
+
+   /// Runs a job on the pool unless the queue is full and the rejection
+   /// policy is `Reject`, in which case the job is handed back.
+   pub fn try_execute<F>(&self, f: F) -> Result<(), Rejected<F>>
+      where
+         F: FnOnce() + Send + 'static
+   {
+      submit(&self.shared, Priority::Normal, f)
+   }
+
+   /// Runs a job on the pool and returns a handle to its result.
+   ///
+   /// ## Input:
+   ///
+   /// * `f` - The job. If it panics, the panic is caught and handed to
+   ///   whoever joins the handle; the worker carries on with the next job.
+   ///   If the job is rejected or dropped because the queue is full,
+   ///   joining the handle returns an error.
+   pub fn spawn<F, T>(&self, f: F) -> JobHandle<T>
+      where
+         F: FnOnce() -> T + Send + 'static,
+         T: Send + 'static
+   {
+      spawn(&self.shared, Priority::Normal, f)
+   }
+
+   /// Runs a job on the pool ahead of, or behind, jobs of other priorities.
+   ///
+   /// ## Input:
+   ///
+   /// * `priority` - Which lane of the queue the job waits in.
+   /// * `f` - The job.
+   pub fn execute_with_priority<F>(&self, priority: Priority, f: F)
+      where
+         F: FnOnce() + Send + 'static
+   {
+      execute(&self.shared, priority, f);
+   }
+
+   /// Runs a job on the pool with the given priority and returns a handle
+   /// to its result.
+   pub fn spawn_with_priority<F, T>(&self, priority: Priority, f: F) -> JobHandle<T>
+      where
+         F: FnOnce() -> T + Send + 'static,
+         T: Send + 'static
+   {
+      spawn(&self.shared, priority, f)
+   }
+
+   /// Returns a Spawner for jobs to submit sub-jobs with.
+   pub fn spawner(&self) -> Spawner {
+      Spawner {
+         shared: Arc::clone(&self.shared),
+      }
+   }
+
+   /// Runs `f` with a Scope for spawning jobs that borrow from the caller,
+   /// and waits for all of them to finish before returning.
+   ///
+   /// ## Input:
+   ///
+   /// * `f` - Spawns the jobs with `Scope::spawn`.
+   ///
+   /// ## Panics
+   ///
+   /// * If `f` panics, the 'scope' function waits for the jobs and then
+   ///   resumes the panic. If a job panicked and its handle was not joined,
+   ///   or a job was rejected because the queue was full, it panics once
+   ///   the jobs have finished.
+   ///
+   /// Called from one of the pool's own jobs, the worker runs queued jobs
+   /// while it waits, so a scope cannot starve the pool of the workers its
+   /// jobs need.
+   pub fn scope<'env, F, R>(&self, f: F) -> R
+      where
+         F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R
+   {
+      scope(&self.shared, f)
+   }
+
+   /// Runs a job on the pool once `delay` has passed.
+   ///
+   /// ## Input:
+   ///
+   /// * `delay` - How long to wait before submitting the job. It is then
+   ///   queued like any other, subject to the rejection policy.
+   /// * `f` - The job.
+   ///
+   /// ## Returns
+   ///
+   /// * A TimerHandle to cancel the job with before it is due.
+   ///
+   /// ## Panics
+   ///
+   /// * The 'execute_after' function will panic if the timer thread cannot
+   ///   be started.
+   pub fn execute_after<F>(&self, delay: Duration, f: F) -> TimerHandle
+      where
+         F: FnOnce() + Send + 'static
+   {
+      execute_after(&self.shared, delay, f)
+   }
+
+   /// Runs a job on the pool every `period`, first once `period` has
+   /// passed, until cancelled. A run still queued or running when the next
+   /// is due causes that next run to be skipped.
+   ///
+   /// ## Panics
+   ///
+   /// * The 'execute_every' function will panic if `period` is zero, or if
+   ///   the timer thread cannot be started.
+   pub fn execute_every<F>(&self, period: Duration, f: F) -> TimerHandle
+      where
+         F: FnMut() + Send + 'static
+   {
+      execute_every(&self.shared, period, f)
+   }
+
+   /// Returns the number of workers running a job.
+   pub fn active_count(&self) -> usize {
+      self.shared.live.load(Ordering::SeqCst).saturating_sub(self.shared.idle.load(Ordering::SeqCst))
+   }
+
+   /// Returns the number of jobs waiting for a worker.
+   pub fn queued_count(&self) -> usize {
+      self.shared.pending.load(Ordering::SeqCst)
+   }
+
+   /// Returns the number of jobs that have finished, including those that
+   /// panicked.
+   pub fn completed_count(&self) -> usize {
+      self.shared.completed.load(Ordering::SeqCst)
+   }
+
+   /// Returns the number of jobs turned away or dropped because the queue
+   /// was full.
+   pub fn rejected_count(&self) -> usize {
+      self.shared.rejected.load(Ordering::SeqCst)
+   }
+
+   /// Returns the number of workers, busy or idle.
+   pub fn thread_count(&self) -> usize {
+      self.shared.live.load(Ordering::SeqCst)
+   }
+
+   /// Returns the queue depth, counters, how long jobs have waited and run,
+   /// and how busy each worker has been.
+   pub fn metrics(&self) -> Metrics {
+      self.shared.metrics()
+   }
+
+   /// Shuts the pool down without waiting for the jobs running on it:
+   /// queued jobs are dropped, and each worker stops once its current job
+   /// returns, on a thread nobody joins. For when a job may never return,
+   /// so that dropping the pool would block.
+   pub fn detach(self) {
+      let threads = self.stop();
+      let discarded = self.shared.discard_queued();
+
+      debug!("Detaching {} workers; dropped {} queued jobs.", threads.len(), discarded);
+   }
+
+   /// Stops the timer, tells every worker to stop once the queues are
+   /// empty, and returns the threads to join.
+   fn stop(&self) -> Vec<(usize, thread::JoinHandle<()>)> {
+      // Set under the slots lock, so that no worker starts after we take
+      // the threads to join.
+      let threads: Vec<(usize, thread::JoinHandle<()>)> = {
+         let mut slots = lock(&self.shared.slots);
+
+         self.shared.shutdown.store(true, Ordering::SeqCst);
+
+         slots.iter_mut()
+            .enumerate()
+            .filter_map(|(id, slot)| slot.thread.take().map(|thread| (id, thread)))
+            .collect()
+      };
+
+      {
+         let _sleep = lock(&self.shared.sleep);
+
+         self.shared.wake.notify_all();
+      }
+
+      {
+         let _room = lock(&self.shared.room);
+
+         self.shared.room_freed.notify_all();
+      }
+
+      self.shared.stop_timers();
+
+      threads
+   }
+}
+
+impl Drop for ThreadPool {
+   fn drop(&mut self) {
+      debug!("Shutting down all workers.");
+
+      for (id, thread) in self.stop() {
+         debug!("Shutting down worker {}", id);
+
+         thread.join().unwrap();
+      }
+   }
+}
+
+impl Spawner {
+   /// Runs a job on the pool, like `ThreadPool::execute`. Once the pool is
+   /// dropped, only its own jobs may submit more; jobs from other threads
+   /// are discarded.
+   pub fn execute<F>(&self, f: F)
+      where
+         F: FnOnce() + Send + 'static
+   {
+      execute(&self.shared, Priority::Normal, f);
+   }
+
+   /// Runs a job on the pool, like `ThreadPool::try_execute`.
+   pub fn try_execute<F>(&self, f: F) -> Result<(), Rejected<F>>
+      where
+         F: FnOnce() + Send + 'static
+   {
+      submit(&self.shared, Priority::Normal, f)
+   }
+
+   /// Runs a job on the pool and returns a handle to its result, like
+   /// `ThreadPool::spawn`.
+   pub fn spawn<F, T>(&self, f: F) -> JobHandle<T>
+      where
+         F: FnOnce() -> T + Send + 'static,
+         T: Send + 'static
+   {
+      spawn(&self.shared, Priority::Normal, f)
+   }
+
+   /// Runs a job on the pool with the given priority, like
+   /// `ThreadPool::execute_with_priority`.
+   pub fn execute_with_priority<F>(&self, priority: Priority, f: F)
+      where
+         F: FnOnce() + Send + 'static
+   {
+      execute(&self.shared, priority, f);
+   }
+
+   /// Runs a job on the pool with the given priority and returns a handle
+   /// to its result, like `ThreadPool::spawn_with_priority`.
+   pub fn spawn_with_priority<F, T>(&self, priority: Priority, f: F) -> JobHandle<T>
+      where
+         F: FnOnce() -> T + Send + 'static,
+         T: Send + 'static
+   {
+      spawn(&self.shared, priority, f)
+   }
+
+   /// Runs `f` with a Scope for jobs that borrow from the caller, like
+   /// `ThreadPool::scope`.
+   pub fn scope<'env, F, R>(&self, f: F) -> R
+      where
+         F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R
+   {
+      scope(&self.shared, f)
+   }
+
+   /// Runs a job on the pool once `delay` has passed, like
+   /// `ThreadPool::execute_after`.
+   pub fn execute_after<F>(&self, delay: Duration, f: F) -> TimerHandle
+      where
+         F: FnOnce() + Send + 'static
+   {
+      execute_after(&self.shared, delay, f)
+   }
+
+   /// Runs a job on the pool every `period`, like
+   /// `ThreadPool::execute_every`.
+   pub fn execute_every<F>(&self, period: Duration, f: F) -> TimerHandle
+      where
+         F: FnMut() + Send + 'static
+   {
+      execute_every(&self.shared, period, f)
+   }
+
+   /// Returns what the pool is doing, like `ThreadPool::metrics`.
+   pub fn metrics(&self) -> Metrics {
+      self.shared.metrics()
+   }
+}
+
+impl fmt::Debug for Spawner {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      f.debug_struct("Spawner").finish()
+   }
+}
+
+fn spawn<F, T>(shared: &Arc<Shared>, priority: Priority, f: F) -> JobHandle<T>
+   where
+      F: FnOnce() -> T + Send + 'static,
+      T: Send + 'static
+{
+   let (sender, receiver) = mpsc::channel();
+
+   execute(shared, priority, move || {
+      let result = panic::catch_unwind(AssertUnwindSafe(f));
+
+      // Nobody is waiting if the handle was dropped.
+      let _ = sender.send(result);
+   });
+
+   JobHandle {
+      receiver,
+   }
+}
+
+impl Shared {
+   /// Returns the index of the current thread if it is one of our workers.
+   fn current_worker(&self) -> Option<usize> {
+      CURRENT.with(|current| match current.get() {
+         Some((shared, index)) if ptr::eq(shared, self) => Some(index),
+         _ => None,
+      })
+   }
+
+   /// Starts a worker if jobs are waiting with no idle worker to take them.
+   fn grow_if_needed(self: &Arc<Shared>) {
+      if self.pending.load(Ordering::SeqCst) > self.idle.load(Ordering::SeqCst) {
+         if let Err(e) = self.grow() {
+            error!("Failed to start a worker: {}", e);
+         }
+      }
+   }
+
+   /// Starts a worker in a free slot, unless every slot is taken or the pool
+   /// is shutting down.
+   fn grow(self: &Arc<Shared>) -> io::Result<()> {
+      let mut slots = lock(&self.slots);
+
+      if self.shutdown.load(Ordering::SeqCst) {
+         return Ok(());
+      }
+
+      let id = match slots.iter().position(|slot| !slot.live) {
+         Some(id) => id,
+         None => return Ok(()),
+      };
+
+      let mut builder = thread::Builder::new();
+
+      if let Some(ref name) = self.config.thread_name {
+         builder = builder.name(format!("{}-{}", name, id));
+      }
+
+      if let Some(stack_size) = self.config.stack_size {
+         builder = builder.stack_size(stack_size);
+      }
+
+      // Counted before it starts, so that a burst of jobs does not start a
+      // worker each.
+      self.live.fetch_add(1, Ordering::SeqCst);
+      self.idle.fetch_add(1, Ordering::SeqCst);
+
+      let worker = Worker {
+         id,
+         shared: Arc::clone(self),
+      };
+
+      // The slot's last worker has stopped, so its stats are ours to reset.
+      let stats = &self.stats[id];
+
+      *lock(&stats.started) = Some(Instant::now());
+      stats.jobs.store(0, Ordering::Relaxed);
+      stats.busy.store(0, Ordering::Relaxed);
+
+      match builder.spawn(move || worker.run()) {
+         Ok(thread) => {
+            // The thread that had this slot has stopped, or is about to.
+            slots[id] = Slot {
+               live: true,
+               thread: Some(thread),
+            };
+
+            Ok(())
+         },
+         Err(e) => {
+            self.live.fetch_sub(1, Ordering::SeqCst);
+            self.idle.fetch_sub(1, Ordering::SeqCst);
+
+            Err(e)
+         },
+      }
+   }
+
+   /// Runs a job on worker `id`, catching any panic, and records how long
+   /// it waited and ran.
+   fn run_job(&self, id: usize, (queued, job): Queued) {
+      let started = Instant::now();
+
+      self.wait_time.record(started - queued);
+      trace!("Worker {} got a job; executing.", id);
+
+      if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+         error!(
+            "Worker {} caught a panic: {}",
+            id,
+            panic_message(&*payload).unwrap_or("(non-string payload)")
+         );
+      }
+
+      let ran = started.elapsed();
+      let stats = &self.stats[id];
+
+      self.run_time.record(ran);
+      stats.jobs.fetch_add(1, Ordering::Relaxed);
+      stats.busy.fetch_add(u64::try_from(ran.as_nanos()).unwrap_or(u64::MAX), Ordering::Relaxed);
+      self.completed.fetch_add(1, Ordering::SeqCst);
+   }
+
+   /// Gives up one worker unless that would leave fewer than `min_threads`.
+   fn retire(&self) -> bool {
+      let mut live = self.live.load(Ordering::SeqCst);
+
+      while live > self.config.min_threads {
+         match self.live.compare_exchange(live, live - 1, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => return true,
+            Err(current) => live = current,
+         }
+      }
+
+      false
+   }
+}
+
+/// Locks a queue even if a panic poisoned it. Jobs never run while a queue
+/// is locked, so the queue itself is always intact.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+   mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/** Waits for the result of a job started with `ThreadPool::spawn`. */
+pub struct JobHandle<T> {
+   receiver: mpsc::Receiver<thread::Result<T>>,
+}
+
+impl<T> JobHandle<T> {
+   /// Waits for the job to finish.
+   ///
+   /// ## Returns
+   ///
+   /// * `Ok(value)` - The value the job returned.
+   /// * `Err(payload)` - The job panicked with `payload`.
+   pub fn join(self) -> thread::Result<T> {
+      match self.receiver.recv() {
+         Ok(result) => result,
+         Err(_) => Err(Box::new("job was dropped without running")),
+      }
+   }
+
+   /// Returns the result if the job has finished, or the handle back if not.
+   pub fn try_join(self) -> Result<thread::Result<T>, JobHandle<T>> {
+      match self.receiver.try_recv() {
+         Ok(result) => Ok(result),
+         Err(mpsc::TryRecvError::Empty) => Err(self),
+         Err(mpsc::TryRecvError::Disconnected) => Ok(Err(Box::new("job was dropped without running"))),
+      }
+   }
+}
+
+impl<T> fmt::Debug for JobHandle<T> {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      f.debug_struct("JobHandle").finish()
+   }
+}
+
+/// Returns the message a panic payload carries, if it is a string.
+pub fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+   payload.downcast_ref::<&str>().cloned()
+      .or_else(|| payload.downcast_ref::<String>().map(|message| message.as_str()))
+}
+
+/** A worker thread, running jobs from its slot. */
+struct Worker {
+   id: usize,
+   shared: Arc<Shared>,
+}
+
+/** Why a worker woke up. */
+enum Wake {
+   Work,
+   Retire,
+   Terminate,
+}
+
+impl Worker {
+   fn run(self) {
+      let shared = &self.shared;
+      let id = self.id;
+
+      CURRENT.with(|current| current.set(Some((&**shared as *const Shared, id))));
+
+      let mut idle_rounds = 0;
+
+      loop {
+         match shared.find_job(id) {
+            Some(queued) => {
+               idle_rounds = 0;
+               shared.idle.fetch_sub(1, Ordering::SeqCst);
+               shared.run_job(id, queued);
+               shared.idle.fetch_add(1, Ordering::SeqCst);
+            },
+            // Going to sleep and being woken costs more than a few
+            // rounds of looking again, which usually find the next job.
+            None if idle_rounds < SPIN_ROUNDS => {
+               idle_rounds += 1;
+               thread::yield_now();
+            },
+            None => match self.sleep() {
+               Wake::Work => {},
+               Wake::Retire => {
+                  debug!("Worker {} was idle for too long; stopping.", id);
+
+                  break;
+               },
+               Wake::Terminate => {
+                  debug!("Worker {} was told to terminate.", id);
+
+                  break;
+               },
+            },
+         }
+      }
+
+      CURRENT.with(|current| current.set(None));
+
+      shared.idle.fetch_sub(1, Ordering::SeqCst);
+      lock(&shared.slots)[id].live = false;
+
+      // A job pushed while we were stopping may have counted on us to run
+      // it.
+      shared.grow_if_needed();
+   }
+
+   /// Waits until there may be a job to run, or until the worker should
+   /// stop: when the pool is shutting down and every job has been taken,
+   /// or when it has been idle for `keep_alive` with more than
+   /// `min_threads` workers running.
+   fn sleep(&self) -> Wake {
+      let shared = &self.shared;
+      let mut sleep = lock(&shared.sleep);
+
+      shared.sleepers.fetch_add(1, Ordering::SeqCst);
+
+      let wake = if shared.pending.load(Ordering::SeqCst) > 0 {
+         Wake::Work
+      } else if shared.shutdown.load(Ordering::SeqCst) {
+         shared.live.fetch_sub(1, Ordering::SeqCst);
+
+         Wake::Terminate
+      } else {
+         let (guard, timeout) = shared.wake.wait_timeout(sleep, shared.config.keep_alive)
+            .unwrap_or_else(PoisonError::into_inner);
+
+         sleep = guard;
+
+         if timeout.timed_out() && shared.pending.load(Ordering::SeqCst) == 0 && shared.retire() {
+            Wake::Retire
+         } else {
+            Wake::Work
+         }
+      };
+
+      shared.sleepers.fetch_sub(1, Ordering::SeqCst);
+
+      drop(sleep);
+
+      wake
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use std::collections::HashSet;
+   use std::sync::Barrier;
+
+   /// Proves `size` workers are alive by running `size` jobs that can only
+   /// finish once all of them are running at the same time.
+   fn assert_live_workers(pool: &ThreadPool, size: usize) {
+      let barrier = Arc::new(Barrier::new(size));
+
+      let handles: Vec<JobHandle<thread::ThreadId>> = (0..size).map(|_| {
+         let barrier = Arc::clone(&barrier);
+
+         pool.spawn(move || {
+            barrier.wait();
+
+            thread::current().id()
+         })
+      }).collect();
+
+      let threads: HashSet<thread::ThreadId> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+
+      assert_eq!(size, threads.len());
+   }
+
+   #[test]
+   fn spawn_returns_results() {
+      let pool = ThreadPool::new(2);
+
+      let handles: Vec<JobHandle<u64>> = (0..10u64).map(|i| pool.spawn(move || i * i)).collect();
+      let results: Vec<u64> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+
+      assert_eq!(vec![0, 1, 4, 9, 16, 25, 36, 49, 64, 81], results);
+   }
+
+   #[test]
+   fn panics_are_returned_to_the_joiner() {
+      let pool = ThreadPool::new(1);
+
+      let handle = pool.spawn(|| -> u32 { panic!("boom {}", 42) });
+      let payload = handle.join().unwrap_err();
+
+      assert_eq!(Some("boom 42"), panic_message(&*payload));
+      assert_eq!(7, pool.spawn(|| 7).join().unwrap());
+   }
+
+   #[test]
+   fn workers_survive_panics() {
+      let pool = ThreadPool::new(4);
+
+      let handles: Vec<JobHandle<()>> = (0..20).map(|_| pool.spawn(|| panic!("boom"))).collect();
+
+      for handle in handles {
+         assert!(handle.join().is_err());
+      }
+
+      for _ in 0..20 {
+         pool.execute(|| panic!("boom"));
+      }
+
+      assert_live_workers(&pool, 4);
+   }
+
+   #[test]
+   fn try_join_before_and_after() {
+      let pool = ThreadPool::new(1);
+      let (sender, receiver) = mpsc::channel::<()>();
+
+      let handle = pool.spawn(move || receiver.recv().unwrap());
+      let handle = handle.try_join().unwrap_err();
+
+      sender.send(()).unwrap();
+
+      assert!(handle.join().is_ok());
+   }
+
+   /// Spawns a binary tree of jobs `depth` levels deep, each level spawned
+   /// by the job above it, counting the leaves.
+   fn tree(spawner: Spawner, depth: u32, leaves: Arc<AtomicUsize>, done: mpsc::Sender<()>) {
+      if depth == 0 {
+         leaves.fetch_add(1, Ordering::SeqCst);
+         done.send(()).unwrap();
+
+         return;
+      }
+
+      for _ in 0..2 {
+         let (child, leaves, done) = (spawner.clone(), Arc::clone(&leaves), done.clone());
+
+         spawner.execute(move || tree(child, depth - 1, leaves, done));
+      }
+   }
+
+   #[test]
+   fn jobs_spawn_sub_jobs() {
+      let pool = ThreadPool::new(4);
+      let leaves = Arc::new(AtomicUsize::new(0));
+      let (done, finished) = mpsc::channel();
+
+      let (spawner, counted) = (pool.spawner(), Arc::clone(&leaves));
+
+      pool.execute(move || tree(spawner, 10, counted, done));
+
+      for _ in 0..1024 {
+         finished.recv().unwrap();
+      }
+
+      assert_eq!(1024, leaves.load(Ordering::SeqCst));
+   }
+
+   #[test]
+   fn idle_workers_steal_sub_jobs() {
+      let pool = ThreadPool::new(4);
+      let barrier = Arc::new(Barrier::new(4));
+      let spawner = pool.spawner();
+      let inner = Arc::clone(&barrier);
+
+      // The sub-jobs land on the parent's own deque, and the parent blocks
+      // until they all run, so the other three workers have to steal them.
+      let handle = pool.spawn(move || {
+         let handles: Vec<JobHandle<()>> = (0..3).map(|_| {
+            let barrier = Arc::clone(&inner);
+
+            spawner.spawn(move || { barrier.wait(); })
+         }).collect();
+
+         inner.wait();
+
+         handles.into_iter().all(|handle| handle.join().is_ok())
+      });
+
+      assert!(handle.join().unwrap());
+   }
+
+   #[test]
+   fn drop_runs_queued_jobs_and_their_sub_jobs() {
+      let count = Arc::new(AtomicUsize::new(0));
+
+      {
+         let pool = ThreadPool::new(2);
+
+         for _ in 0..100 {
+            let (spawner, count) = (pool.spawner(), Arc::clone(&count));
+
+            pool.execute(move || {
+               let inner = Arc::clone(&count);
+
+               spawner.execute(move || { inner.fetch_add(1, Ordering::SeqCst); });
+               count.fetch_add(1, Ordering::SeqCst);
+            });
+         }
+      }
+
+      assert_eq!(200, count.load(Ordering::SeqCst));
+   }
+
+   #[test]
+   fn spawner_outlives_pool() {
+      let pool = ThreadPool::new(1);
+      let spawner = pool.spawner();
+
+      drop(pool);
+
+      assert!(spawner.spawn(|| 1).join().is_err());
+   }
+
+   #[test]
+   fn detach_drops_queued_jobs_and_leaves_running_ones() {
+      let (pool, release) = blocked_pool(Duration::from_secs(1));
+      let spawner = pool.spawner();
+      let queued = pool.spawn(|| 1);
+
+      pool.detach();
+
+      assert!(queued.join().is_err());
+      assert_eq!(1, spawner.metrics().threads);
+
+      release.send(()).unwrap();
+
+      assert!(eventually(|| spawner.metrics().threads == 0));
+      assert_eq!(0, spawner.metrics().queued);
+   }
+
+   /// Polls `condition` for up to five seconds.
+   pub(super) fn eventually<F: Fn() -> bool>(condition: F) -> bool {
+      let start = std::time::Instant::now();
+
+      while start.elapsed() < Duration::from_secs(5) {
+         if condition() {
+            return true;
+         }
+
+         thread::sleep(Duration::from_millis(5));
+      }
+
+      false
+   }
+
+   #[test]
+   fn grows_under_backlog_and_shrinks_when_idle() {
+      let pool = ThreadPool::builder()
+         .min_threads(1)
+         .max_threads(4)
+         .keep_alive(Duration::from_millis(50))
+         .build();
+
+      assert_eq!(1, pool.thread_count());
+
+      // Four jobs that wait for each other need four workers.
+      assert_live_workers(&pool, 4);
+      assert_eq!(4, pool.thread_count());
+
+      assert!(eventually(|| pool.thread_count() == 1));
+
+      assert_live_workers(&pool, 3);
+   }
+
+   #[test]
+   fn never_exceeds_max_threads() {
+      let pool = ThreadPool::builder().min_threads(0).max_threads(2).build();
+      let (release, released) = mpsc::channel::<()>();
+      let released = Arc::new(Mutex::new(released));
+
+      assert_eq!(0, pool.thread_count());
+
+      let handles: Vec<JobHandle<()>> = (0..5).map(|_| {
+         let released = Arc::clone(&released);
+
+         pool.spawn(move || released.lock().unwrap().recv().unwrap())
+      }).collect();
+
+      assert!(eventually(|| pool.active_count() == 2 && pool.queued_count() == 3));
+      assert_eq!(2, pool.thread_count());
+
+      for _ in 0..5 {
+         release.send(()).unwrap();
+      }
+
+      for handle in handles {
+         handle.join().unwrap();
+      }
+
+      assert!(eventually(|| pool.completed_count() == 5 && pool.active_count() == 0));
+      assert_eq!(0, pool.queued_count());
+   }
+
+   #[test]
+   fn counts_jobs() {
+      let pool = ThreadPool::builder().min_threads(1).max_threads(1).build();
+      let (release, released) = mpsc::channel::<()>();
+
+      pool.execute(move || released.recv().unwrap());
+
+      for _ in 0..3 {
+         pool.execute(|| panic!("boom"));
+      }
+
+      assert!(eventually(|| pool.active_count() == 1));
+      assert_eq!(3, pool.queued_count());
+      assert_eq!(0, pool.completed_count());
+
+      release.send(()).unwrap();
+
+      assert!(eventually(|| pool.completed_count() == 4));
+      assert_eq!(0, pool.queued_count());
+   }
+
+   #[test]
+   fn names_and_stack_size() {
+      let pool = ThreadPool::builder()
+         .min_threads(2)
+         .max_threads(2)
+         .thread_name("hello-pool")
+         .stack_size(32 << 20)
+         .build();
+
+      let name = pool.spawn(|| thread::current().name().map(|name| name.to_string())).join().unwrap();
+
+      assert!(name == Some("hello-pool-0".to_string()) || name == Some("hello-pool-1".to_string()));
+
+      // Far more than the default stack of 2 MiB.
+      let sum = pool.spawn(|| {
+         let big = [1u8; 16 << 20];
+
+         std::hint::black_box(&big).iter().map(|&b| b as usize).sum::<usize>()
+      }).join().unwrap();
+
+      assert_eq!(16 << 20, sum);
+   }
+
+   /// Returns a one-thread pool whose worker is busy until the returned
+   /// sender is used.
+   pub(super) fn blocked_pool(aging: Duration) -> (ThreadPool, mpsc::Sender<()>) {
+      let pool = ThreadPool::builder().min_threads(1).max_threads(1).aging(aging).build();
+      let (release, gate) = mpsc::channel();
+      let (started, running) = mpsc::channel();
+
+      pool.execute(move || {
+         started.send(()).unwrap();
+         gate.recv().unwrap();
+      });
+
+      running.recv().unwrap();
+
+      (pool, release)
+   }
+}
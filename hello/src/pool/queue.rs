@@ -0,0 +1,649 @@
+/* System includes */
+use std::error;
+use std::fmt;
+use std::thread;
+
+use std::cmp::Reverse;
+
+use std::collections::VecDeque;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use std::time::{Duration, Instant};
+
+/* extern create usings */
+use super::{lock, Job, Queued, Shared};
+
+/** How soon a job runs relative to others queued from outside the pool. */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+   Low,
+   #[default]
+   Normal,
+   High,
+}
+
+/** A shard of the injector: a queue per priority, oldest job first, and
+ * how many jobs it holds, so that workers pass it by when it is empty
+ * without locking it.
+ */
+#[derive(Default)]
+pub(super) struct Shard {
+   lanes: Mutex<Lanes>,
+   len: AtomicUsize,
+}
+
+/// How an injected job ranks for a worker: by its priority once aged, then
+/// its lane, then the older first.
+type Aged = (u128, usize, Reverse<Instant>);
+
+#[derive(Default)]
+struct Lanes {
+   lanes: [VecDeque<Queued>; 3],
+}
+
+/** What to do with a job submitted when the queue is full. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionPolicy {
+   /// Wait until a worker takes a job from the queue.
+   Block,
+   /// Turn the job away: `try_execute` returns it in an error, and
+   /// `execute` and `spawn` drop it.
+   Reject,
+   /// Drop the job that has been queued longest to make room.
+   DropOldest,
+   /// Run the job on the submitting thread instead.
+   CallerRuns,
+}
+
+/** A job turned away because the queue was full. */
+pub struct Rejected<F> {
+   job: F,
+}
+
+/** What `Shared::admit` decided to do with a job. */
+enum Admit {
+   /// Queue it; it has been counted in `pending`.
+   Queue,
+   Reject,
+   RunHere,
+   /// Drop it, because the pool is shutting down.
+   Discard,
+}
+
+/// Hands each submitting thread its own injector shard, in turn.
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+   /// The injector shard the current thread submits to, modulo the number
+   /// of shards. Keeping to one shard keeps a thread's jobs in order.
+   static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(super) fn execute<F>(shared: &Arc<Shared>, priority: Priority, f: F)
+   where
+      F: FnOnce() + Send + 'static
+{
+   if submit(shared, priority, f).is_err() {
+      warn!("Rejected a job; the queue is full.");
+   }
+}
+
+/// Queues a job, or deals with it as the rejection policy says if the
+/// queue is full.
+pub(super) fn submit<F>(shared: &Arc<Shared>, priority: Priority, f: F) -> Result<(), Rejected<F>>
+   where
+      F: FnOnce() + Send + 'static
+{
+   match shared.admit() {
+      Admit::Queue => shared.push(priority, Box::new(f)),
+      Admit::Reject => return Err(Rejected {
+         job: f,
+      }),
+      Admit::RunHere => f(),
+      Admit::Discard => {},
+   }
+
+   Ok(())
+}
+
+impl Lanes {
+   fn push(&mut self, priority: Priority, job: Job) {
+      self.lanes[priority as usize].push_back((Instant::now(), job));
+   }
+
+   /// Picks the lane whose front job ranks first, and ranks that job
+   /// against the picks of other shards.
+   fn aged(&self, aging: Duration, now: Instant) -> Option<(Aged, usize)> {
+      self.lanes
+         .iter()
+         .enumerate()
+         .filter_map(|(lane, jobs)| jobs.front().map(|&(queued, _)| {
+            let waited = now.saturating_duration_since(queued).as_nanos() / aging.as_nanos();
+
+            ((lane as u128 + waited, lane, Reverse(queued)), lane)
+         }))
+         .max()
+   }
+
+   /// Picks the lowest non-empty lane, and ranks its front job against the
+   /// picks of other shards: the lower and then the older first.
+   fn lowest(&self) -> Option<(Reverse<(usize, Instant)>, usize)> {
+      let lane = self.lanes.iter().position(|jobs| !jobs.is_empty())?;
+
+      Some((Reverse((lane, self.lanes[lane][0].0)), lane))
+   }
+
+   fn take(&mut self, lane: usize) -> Option<(Priority, Queued)> {
+      let priority = [Priority::Low, Priority::Normal, Priority::High][lane];
+
+      self.lanes[lane].pop_front().map(|queued| (priority, queued))
+   }
+}
+
+impl<F> Rejected<F> {
+   /// Returns the job that was turned away.
+   pub fn into_inner(self) -> F {
+      self.job
+   }
+}
+
+impl<F> fmt::Debug for Rejected<F> {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      f.debug_struct("Rejected").finish()
+   }
+}
+
+impl<F> fmt::Display for Rejected<F> {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "the thread pool's queue is full")
+   }
+}
+
+impl<F> error::Error for Rejected<F> {}
+
+impl Shared {
+   /// Decides what to do with a job about to be submitted, and if it is to
+   /// be queued, counts it in `pending`. Counting it before it is queued
+   /// means taking it never finds `pending` at zero.
+   fn admit(&self) -> Admit {
+      let worker = self.current_worker().is_some();
+
+      if !worker && self.shutdown.load(Ordering::SeqCst) {
+         return Admit::Discard;
+      }
+
+      let capacity = match self.config.queue_capacity {
+         Some(capacity) if !worker => capacity,
+         _ => {
+            self.pending.fetch_add(1, Ordering::SeqCst);
+
+            return Admit::Queue;
+         },
+      };
+
+      loop {
+         let pending = self.pending.load(Ordering::SeqCst);
+
+         if pending < capacity {
+            if self.pending.compare_exchange(pending, pending + 1, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+               return Admit::Queue;
+            }
+
+            continue;
+         }
+
+         match self.config.rejection_policy {
+            RejectionPolicy::Block => {
+               self.wait_for_room(capacity);
+
+               if self.shutdown.load(Ordering::SeqCst) {
+                  return Admit::Discard;
+               }
+            },
+            RejectionPolicy::Reject => {
+               self.rejected.fetch_add(1, Ordering::SeqCst);
+
+               return Admit::Reject;
+            },
+            RejectionPolicy::CallerRuns => return Admit::RunHere,
+            RejectionPolicy::DropOldest => match self.take_oldest() {
+               // The new job takes the dropped job's place in `pending`.
+               Some(oldest) => {
+                  self.rejected.fetch_add(1, Ordering::SeqCst);
+                  drop(oldest);
+
+                  return Admit::Queue;
+               },
+               // Counted, but not queued yet.
+               None => thread::yield_now(),
+            },
+         }
+      }
+   }
+
+   /// Waits until fewer than `capacity` jobs are queued, or the pool is
+   /// shutting down.
+   fn wait_for_room(&self, capacity: usize) {
+      let mut room = lock(&self.room);
+
+      self.blocked.fetch_add(1, Ordering::SeqCst);
+
+      // Pairs with the check of `blocked` in `find_job`, as `sleepers` does
+      // in `push`.
+      while self.pending.load(Ordering::SeqCst) >= capacity && !self.shutdown.load(Ordering::SeqCst) {
+         room = self.room_freed.wait(room).unwrap_or_else(PoisonError::into_inner);
+      }
+
+      self.blocked.fetch_sub(1, Ordering::SeqCst);
+   }
+
+   /// Takes the oldest job of the lowest priority queued from outside the
+   /// pool, or failing that the oldest job in any worker's deque, without
+   /// uncounting it.
+   fn take_oldest(&self) -> Option<Job> {
+      let injected = self.take_injected(Lanes::lowest);
+
+      injected
+         .or_else(|| self.locals.iter().find_map(|local| lock(local).pop_front()))
+         .map(|(_, job)| job)
+   }
+
+   /// Drops every queued job, and returns how many there were.
+   pub(super) fn discard_queued(&self) -> usize {
+      let mut discarded = 0;
+
+      while let Some(job) = self.take_oldest() {
+         self.pending.fetch_sub(1, Ordering::SeqCst);
+         discarded += 1;
+
+         drop(job);
+      }
+
+      discarded
+   }
+
+   /// Takes the injected job that `pick` ranks first across the shards,
+   /// keeping count of the `High` lane. `pick` names the lane a shard would
+   /// give a job from, and how that job ranks.
+   fn take_injected<P, R>(&self, pick: P) -> Option<Queued>
+      where
+         P: Fn(&Lanes) -> Option<(R, usize)>,
+         R: Ord
+   {
+      loop {
+         // Each shard is locked on its own, so the job picked may be gone
+         // once its shard is locked again; then pick again.
+         let (_, shard) = self.injector
+            .iter()
+            .enumerate()
+            .filter(|(_, shard)| shard.len.load(Ordering::SeqCst) > 0)
+            .filter_map(|(index, shard)| pick(&lock(&shard.lanes)).map(|(rank, _)| (rank, index)))
+            .max_by(|a, b| a.0.cmp(&b.0))?;
+
+         let shard = &self.injector[shard];
+         let mut lanes = lock(&shard.lanes);
+
+         if let Some((priority, queued)) = pick(&lanes).and_then(|(_, lane)| lanes.take(lane)) {
+            shard.len.fetch_sub(1, Ordering::SeqCst);
+
+            if priority == Priority::High {
+               self.urgent.fetch_sub(1, Ordering::SeqCst);
+            }
+
+            return Some(queued);
+         }
+      }
+   }
+
+   /// Queues a job already counted by `admit`.
+   fn push(self: &Arc<Shared>, priority: Priority, job: Job) {
+      let worker = self.current_worker();
+
+      match worker {
+         Some(index) if priority == Priority::Normal => lock(&self.locals[index]).push_back((Instant::now(), job)),
+         _ => {
+            let index = worker.unwrap_or_else(|| SHARD.with(|&shard| shard));
+            let shard = &self.injector[index % self.injector.len()];
+            let mut lanes = lock(&shard.lanes);
+
+            if priority == Priority::High {
+               self.urgent.fetch_add(1, Ordering::SeqCst);
+            }
+
+            lanes.push(priority, job);
+            shard.len.fetch_add(1, Ordering::SeqCst);
+         },
+      }
+
+      // Pairs with the check of `pending` in `Worker::sleep`: either the
+      // sleeper sees the job, or we see the sleeper and wake it.
+      if self.sleepers.load(Ordering::SeqCst) > 0 {
+         let _sleep = lock(&self.sleep);
+
+         self.wake.notify_one();
+      }
+
+      self.grow_if_needed();
+   }
+
+   /// Finds the next job for worker `index`: an urgent injected job, its
+   /// own newest job, the next injected job by priority, or the oldest job
+   /// of another worker.
+   pub(super) fn find_job(&self, index: usize) -> Option<Queued> {
+      let aging = self.config.aging;
+      let now = Instant::now();
+      let urgent = if self.urgent.load(Ordering::SeqCst) > 0 {
+         self.take_injected(|lanes| lanes.aged(aging, now))
+      } else {
+         None
+      };
+
+      // Each queue is locked on its own; holding one while locking another
+      // would deadlock two workers stealing from each other.
+      let own = urgent.or_else(|| lock(&self.locals[index]).pop_back());
+      let job = own
+         .or_else(|| self.take_injected(|lanes| lanes.aged(aging, now)))
+         .or_else(|| {
+            let count = self.locals.len();
+
+            (1..count)
+               .map(|offset| (index + offset) % count)
+               .find_map(|victim| lock(&self.locals[victim]).pop_front())
+         });
+
+      if job.is_some() {
+         self.pending.fetch_sub(1, Ordering::SeqCst);
+
+         if self.blocked.load(Ordering::SeqCst) > 0 {
+            let _room = lock(&self.room);
+
+            self.room_freed.notify_one();
+         }
+      }
+
+      job
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use pool::tests::{blocked_pool, eventually};
+   use pool::{JobHandle, ThreadPool};
+
+   use std::sync::atomic::AtomicBool;
+   use std::sync::{mpsc, Mutex};
+
+   /// Returns a one-thread pool with a queue of one, its worker busy until
+   /// the returned sender is used, and one job queued behind it.
+   fn full_pool(policy: RejectionPolicy) -> (ThreadPool, mpsc::Sender<()>, JobHandle<()>) {
+      let pool = ThreadPool::builder()
+         .min_threads(1)
+         .max_threads(1)
+         .queue_capacity(1)
+         .rejection_policy(policy)
+         .build();
+
+      let (release, released) = mpsc::channel();
+
+      pool.execute(move || released.recv().unwrap());
+
+      assert!(eventually(|| pool.active_count() == 1));
+
+      let queued = pool.spawn(|| {});
+
+      assert_eq!(1, pool.queued_count());
+
+      (pool, release, queued)
+   }
+
+   #[test]
+   fn block_waits_for_room() {
+      let (pool, release, queued) = full_pool(RejectionPolicy::Block);
+      let spawner = pool.spawner();
+      let submitted = Arc::new(AtomicBool::new(false));
+      let flag = Arc::clone(&submitted);
+
+      let submitter = thread::spawn(move || {
+         let handle = spawner.spawn(|| 3);
+
+         flag.store(true, Ordering::SeqCst);
+
+         handle.join().unwrap()
+      });
+
+      thread::sleep(Duration::from_millis(50));
+
+      assert!(!submitted.load(Ordering::SeqCst));
+
+      release.send(()).unwrap();
+
+      assert_eq!(3, submitter.join().unwrap());
+      assert!(queued.join().is_ok());
+      assert_eq!(0, pool.rejected_count());
+   }
+
+   #[test]
+   fn reject_hands_the_job_back() {
+      let (pool, release, queued) = full_pool(RejectionPolicy::Reject);
+      let ran = Arc::new(AtomicBool::new(false));
+      let flag = Arc::clone(&ran);
+
+      let rejected = pool.try_execute(move || flag.store(true, Ordering::SeqCst)).unwrap_err();
+
+      assert_eq!("the thread pool's queue is full", rejected.to_string());
+
+      rejected.into_inner()();
+
+      assert!(ran.load(Ordering::SeqCst));
+      assert!(pool.spawn(|| {}).join().is_err());
+      assert_eq!(2, pool.rejected_count());
+
+      release.send(()).unwrap();
+
+      assert!(queued.join().is_ok());
+   }
+
+   #[test]
+   fn drop_oldest_makes_room() {
+      let (pool, release, queued) = full_pool(RejectionPolicy::DropOldest);
+
+      let newest = pool.spawn(|| 5);
+
+      assert_eq!(1, pool.queued_count());
+      assert_eq!(1, pool.rejected_count());
+
+      release.send(()).unwrap();
+
+      assert!(queued.join().is_err());
+      assert_eq!(5, newest.join().unwrap());
+   }
+
+   #[test]
+   fn caller_runs_when_full() {
+      let (pool, release, queued) = full_pool(RejectionPolicy::CallerRuns);
+      let caller = thread::current().id();
+
+      assert_eq!(caller, pool.spawn(|| thread::current().id()).join().unwrap());
+      assert_eq!(1, pool.queued_count());
+
+      release.send(()).unwrap();
+
+      assert!(queued.join().is_ok());
+      assert_ne!(caller, pool.spawn(|| thread::current().id()).join().unwrap());
+   }
+
+   #[test]
+   fn workers_bypass_the_bound() {
+      let (pool, release, queued) = full_pool(RejectionPolicy::Reject);
+      let spawner = pool.spawner();
+
+      release.send(()).unwrap();
+      queued.join().unwrap();
+
+      // Ten sub-jobs from one job, with room for one in the queue.
+      let handles = pool.spawn(move || {
+         (0..10).map(|i| spawner.spawn(move || i)).collect::<Vec<JobHandle<usize>>>()
+      });
+
+      let sum: usize = handles.join().unwrap().into_iter().map(|handle| handle.join().unwrap()).sum();
+
+      assert_eq!(45, sum);
+      assert_eq!(0, pool.rejected_count());
+   }
+
+   #[test]
+   fn high_priority_jobs_jump_the_queue() {
+      let (pool, release) = blocked_pool(Duration::from_secs(60));
+      let (sender, receiver) = mpsc::channel();
+
+      for &priority in &[Priority::Low, Priority::Normal, Priority::High] {
+         for i in 0..5 {
+            let sender = sender.clone();
+
+            pool.execute_with_priority(priority, move || sender.send((priority, i)).unwrap());
+         }
+      }
+
+      release.send(()).unwrap();
+
+      let order: Vec<(Priority, usize)> = (0..15).map(|_| receiver.recv().unwrap()).collect();
+      let mut expected = Vec::new();
+
+      for &priority in &[Priority::High, Priority::Normal, Priority::Low] {
+         expected.extend((0..5).map(|i| (priority, i)));
+      }
+
+      assert_eq!(expected, order);
+   }
+
+   #[test]
+   fn priorities_hold_across_submitting_threads() {
+      let pool = ThreadPool::builder().min_threads(2).max_threads(2).aging(Duration::from_secs(60)).build();
+      let (started, running) = mpsc::channel();
+      let gates: Vec<mpsc::Sender<()>> = (0..2).map(|_| {
+         let (release, gate) = mpsc::channel();
+         let started = started.clone();
+
+         pool.execute(move || {
+            started.send(()).unwrap();
+            gate.recv().unwrap();
+         });
+
+         release
+      }).collect();
+
+      running.recv().unwrap();
+      running.recv().unwrap();
+
+      // Each thread submits to a shard of its own, unless they happen to
+      // share one.
+      let (sender, receiver) = mpsc::channel();
+      let submitters: Vec<thread::JoinHandle<()>> = [Priority::Low, Priority::Low, Priority::High, Priority::High]
+         .iter()
+         .map(|&priority| {
+            let (spawner, sender) = (pool.spawner(), sender.clone());
+
+            thread::spawn(move || for _ in 0..5 {
+               let sender = sender.clone();
+
+               spawner.execute_with_priority(priority, move || sender.send(priority).unwrap());
+            })
+         })
+         .collect();
+
+      for submitter in submitters {
+         submitter.join().unwrap();
+      }
+
+      gates[0].send(()).unwrap();
+
+      let order: Vec<Priority> = (0..20).map(|_| receiver.recv().unwrap()).collect();
+
+      assert!(order[..10].iter().all(|&priority| priority == Priority::High));
+      assert!(order[10..].iter().all(|&priority| priority == Priority::Low));
+
+      gates[1].send(()).unwrap();
+   }
+
+   #[test]
+   fn high_priority_jobs_finish_first_under_load() {
+      let pool = ThreadPool::new(2);
+      let finished = Arc::new(Mutex::new(Vec::new()));
+
+      for i in 0..200 {
+         let finished = Arc::clone(&finished);
+
+         pool.execute_with_priority(Priority::Low, move || {
+            thread::sleep(Duration::from_millis(1));
+            lock(&finished).push(Some(i));
+         });
+      }
+
+      let handles: Vec<JobHandle<()>> = (0..10).map(|_| {
+         let finished = Arc::clone(&finished);
+
+         pool.spawn_with_priority(Priority::High, move || lock(&finished).push(None))
+      }).collect();
+
+      for handle in handles {
+         handle.join().unwrap();
+      }
+
+      // All ten urgent jobs are done while most of the bulk is still queued.
+      let finished = lock(&finished);
+
+      assert_eq!(10, finished.iter().filter(|job| job.is_none()).count());
+      assert!(finished.len() < 100, "{} jobs finished first", finished.len());
+   }
+
+   #[test]
+   fn aging_stops_starvation() {
+      let (pool, release) = blocked_pool(Duration::from_millis(20));
+      let (sender, receiver) = mpsc::channel();
+      let low = sender.clone();
+
+      pool.execute_with_priority(Priority::Low, move || low.send("low").unwrap());
+
+      // Long enough to outrank anything queued from now on.
+      thread::sleep(Duration::from_millis(100));
+
+      for _ in 0..5 {
+         let sender = sender.clone();
+
+         pool.execute_with_priority(Priority::High, move || sender.send("high").unwrap());
+      }
+
+      release.send(()).unwrap();
+
+      assert_eq!("low", receiver.recv().unwrap());
+   }
+
+   #[test]
+   fn urgent_jobs_run_before_a_workers_own() {
+      let (pool, release) = blocked_pool(Duration::from_secs(60));
+      let spawner = pool.spawner();
+      let (sender, receiver) = mpsc::channel();
+
+      pool.execute(move || {
+         for i in 0..3 {
+            let sender = sender.clone();
+
+            spawner.execute(move || sender.send(Some(i)).unwrap());
+         }
+
+         let sender = sender.clone();
+
+         spawner.execute_with_priority(Priority::High, move || sender.send(None).unwrap());
+      });
+
+      release.send(()).unwrap();
+
+      let order: Vec<Option<usize>> = (0..4).map(|_| receiver.recv().unwrap()).collect();
+
+      assert_eq!(vec![None, Some(2), Some(1), Some(0)], order);
+   }
+}
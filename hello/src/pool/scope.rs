@@ -0,0 +1,348 @@
+/* System includes */
+use std::fmt;
+use std::mem;
+use std::thread;
+
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+/* extern create usings */
+use super::{execute, lock, Job, Priority, Shared};
+
+/** Spawns jobs that may borrow from the caller of `ThreadPool::scope`. */
+pub struct Scope<'scope, 'env: 'scope> {
+   shared: Arc<Shared>,
+   data: Arc<ScopeData>,
+   scope: PhantomData<&'scope mut &'scope ()>,
+   env: PhantomData<&'env mut &'env ()>,
+}
+
+struct ScopeData {
+   /// Jobs that have not yet finished or been dropped.
+   running: Mutex<usize>,
+   /// Notified whenever one of them does.
+   done: Condvar,
+   /// Whether a job panicked, or never ran, with nobody joining it.
+   failed: AtomicBool,
+}
+
+/** Waits for the result of a job started with `Scope::spawn`. */
+pub struct ScopedJobHandle<'scope, T: 'scope> {
+   packet: Arc<Packet<'scope, T>>,
+}
+
+/** Where a scoped job leaves its result for its handle. */
+struct Packet<'scope, T: 'scope> {
+   shared: Arc<Shared>,
+   scope: Arc<ScopeData>,
+   result: Mutex<Option<thread::Result<T>>>,
+   finished: Condvar,
+   _scope: PhantomData<&'scope ()>,
+}
+
+/** Marks a scoped job finished when dropped, whether or not it ran. */
+struct Finish<'scope, T: 'scope> {
+   packet: Option<Arc<Packet<'scope, T>>>,
+   scope: Arc<ScopeData>,
+}
+
+pub(super) fn scope<'env, F, R>(shared: &Arc<Shared>, f: F) -> R
+   where
+      F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R
+{
+   let scope = Scope {
+      shared: Arc::clone(shared),
+      data: Arc::new(ScopeData {
+         running: Mutex::new(0),
+         done: Condvar::new(),
+         failed: AtomicBool::new(false),
+      }),
+      scope: PhantomData,
+      env: PhantomData,
+   };
+
+   let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+   scope.wait();
+
+   match result {
+      Err(payload) => panic::resume_unwind(payload),
+      Ok(_) if scope.data.failed.load(Ordering::SeqCst) => panic!("a scoped job panicked or was dropped"),
+      Ok(value) => value,
+   }
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+   /// Runs a job on the pool. Unlike `ThreadPool::spawn`, the job may borrow
+   /// anything that outlives the scope.
+   pub fn spawn<F, T>(&'scope self, f: F) -> ScopedJobHandle<'scope, T>
+      where
+         F: FnOnce() -> T + Send + 'scope,
+         T: Send + 'scope
+   {
+      *lock(&self.data.running) += 1;
+
+      let packet = Arc::new(Packet {
+         shared: Arc::clone(&self.shared),
+         scope: Arc::clone(&self.data),
+         result: Mutex::new(None),
+         finished: Condvar::new(),
+         _scope: PhantomData,
+      });
+
+      let finish = Finish {
+         packet: Some(Arc::clone(&packet)),
+         scope: Arc::clone(&self.data),
+      };
+
+      // One capture, so that a job dropped without running drops `f`
+      // before `finish` tells the scope it is done.
+      let parts = (f, finish);
+
+      let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+         let (f, finish) = parts;
+         let result = panic::catch_unwind(AssertUnwindSafe(f));
+
+         if let Some(ref packet) = finish.packet {
+            *lock(&packet.result) = Some(result);
+         }
+      });
+
+      // SAFETY: `ThreadPool::scope` does not return, even by unwinding,
+      // until every job has run or been dropped, and a job lets go of its
+      // packet before it counts as finished, so neither the job nor a
+      // result nobody holds a handle to outlives the data it borrows.
+      let job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+
+      execute(&self.shared, Priority::Normal, job);
+
+      ScopedJobHandle {
+         packet,
+      }
+   }
+
+   /// Waits until every job has finished or been dropped, running the
+   /// pool's jobs meanwhile if this thread is one of its workers. Handles
+   /// play no part, so a leaked one cannot keep the scope waiting.
+   fn wait(&self) {
+      drop(self.shared.wait_while(&self.data.running, &self.data.done, |running| *running > 0));
+   }
+}
+
+impl<'scope, 'env> fmt::Debug for Scope<'scope, 'env> {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      f.debug_struct("Scope").finish()
+   }
+}
+
+impl<'scope, T> ScopedJobHandle<'scope, T> {
+   /// Waits for the job to finish.
+   ///
+   /// ## Returns
+   ///
+   /// * `Ok(value)` - The value the job returned.
+   /// * `Err(payload)` - The job panicked with `payload`, or was dropped
+   ///   without running.
+   pub fn join(self) -> thread::Result<T> {
+      let packet = &self.packet;
+      let mut result = packet.shared.wait_while(&packet.result, &packet.finished, |result| result.is_none());
+
+      result.take().expect("the job finished without a result")
+   }
+}
+
+impl<'scope, T> fmt::Debug for ScopedJobHandle<'scope, T> {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      f.debug_struct("ScopedJobHandle").finish()
+   }
+}
+
+impl<'scope, T> Drop for Finish<'scope, T> {
+   fn drop(&mut self) {
+      if let Some(packet) = self.packet.take() {
+         {
+            let mut result = lock(&packet.result);
+
+            if result.is_none() {
+               *result = Some(Err(Box::new("job was dropped without running")));
+            }
+
+            packet.finished.notify_all();
+         }
+
+         // With the handle gone, this drops the result, which may borrow
+         // from the scope, so it goes before the scope hears the job is
+         // done.
+         drop(packet);
+      }
+
+      let mut running = lock(&self.scope.running);
+
+      *running -= 1;
+
+      // Every time, not just for the last job: a worker waiting on the
+      // scope looks for queued jobs to run whenever it wakes.
+      self.scope.done.notify_all();
+   }
+}
+
+impl<'scope, T> Drop for Packet<'scope, T> {
+   fn drop(&mut self) {
+      // A result still here was never joined.
+      let result = self.result.get_mut().unwrap_or_else(PoisonError::into_inner).take();
+
+      if let Some(Err(_)) = result {
+         self.scope.failed.store(true, Ordering::SeqCst);
+      }
+   }
+}
+
+impl Shared {
+   /// Waits on `condvar` while `condition` holds for the value in `mutex`.
+   /// On one of the pool's workers, runs queued jobs instead of blocking, so
+   /// that a job waiting on other jobs cannot starve the pool, and looks
+   /// for more each time `condvar` is notified.
+   fn wait_while<'a, T, C>(&self, mutex: &'a Mutex<T>, condvar: &Condvar, mut condition: C) -> MutexGuard<'a, T>
+      where
+         C: FnMut(&mut T) -> bool
+   {
+      let worker = self.current_worker();
+      let mut guard = lock(mutex);
+
+      while condition(&mut *guard) {
+         guard = match worker.map(|id| (id, self.find_job(id))) {
+            Some((id, Some(queued))) => {
+               drop(guard);
+               self.run_job(id, queued);
+
+               lock(mutex)
+            },
+            // Nothing to help with; the jobs are running on other workers,
+            // which notify `condvar` as they finish.
+            _ => condvar.wait(guard).unwrap_or_else(PoisonError::into_inner),
+         };
+      }
+
+      guard
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use pool::{panic_message, ThreadPool};
+
+   use std::sync::atomic::AtomicUsize;
+   use std::time::Duration;
+
+   #[test]
+   fn scoped_jobs_borrow_from_the_caller() {
+      let pool = ThreadPool::new(4);
+      let mut numbers: Vec<usize> = (0..100).collect();
+      let offset = 1;
+
+      let total = pool.scope(|s| {
+         let handles: Vec<_> = numbers.chunks_mut(10).map(|chunk| {
+            s.spawn(move || {
+               for number in chunk.iter_mut() {
+                  *number += offset;
+               }
+
+               chunk.iter().sum::<usize>()
+            })
+         }).collect();
+
+         handles.into_iter().map(|handle| handle.join().unwrap()).sum::<usize>()
+      });
+
+      assert_eq!(5050, total);
+      assert_eq!((1..101).collect::<Vec<usize>>(), numbers);
+   }
+
+   #[test]
+   fn scope_waits_for_every_job_even_on_panic() {
+      let pool = ThreadPool::new(2);
+      let finished = AtomicUsize::new(0);
+
+      let result = panic::catch_unwind(AssertUnwindSafe(|| {
+         pool.scope(|s| {
+            for i in 0..8 {
+               let finished = &finished;
+
+               s.spawn(move || {
+                  thread::sleep(Duration::from_millis(10));
+                  finished.fetch_add(1, Ordering::SeqCst);
+
+                  if i == 3 {
+                     panic!("job 3 failed");
+                  }
+               });
+            }
+
+            panic!("the scope failed");
+         })
+      }));
+
+      assert_eq!(Some("the scope failed"), panic_message(&*result.unwrap_err()));
+      assert_eq!(8, finished.load(Ordering::SeqCst));
+   }
+
+   #[test]
+   fn forgotten_handles_do_not_hold_up_the_scope() {
+      let pool = ThreadPool::new(2);
+      let finished = AtomicUsize::new(0);
+
+      pool.scope(|s| {
+         for _ in 0..4 {
+            mem::forget(s.spawn(|| {
+               thread::sleep(Duration::from_millis(10));
+               finished.fetch_add(1, Ordering::SeqCst)
+            }));
+         }
+      });
+
+      assert_eq!(4, finished.load(Ordering::SeqCst));
+   }
+
+   #[test]
+   fn unjoined_panics_propagate() {
+      let pool = ThreadPool::new(2);
+
+      let result = panic::catch_unwind(AssertUnwindSafe(|| {
+         pool.scope(|s| {
+            s.spawn(|| panic!("unjoined"));
+         })
+      }));
+
+      assert!(result.is_err());
+
+      // A joined panic has been handled by whoever joined it.
+      let joined = pool.scope(|s| s.spawn(|| panic!("joined")).join().is_err());
+
+      assert!(joined);
+   }
+
+   #[test]
+   fn scopes_nest_inside_jobs() {
+      let pool = Arc::new(ThreadPool::new(1));
+      let inner = Arc::clone(&pool);
+
+      // The only worker waits on the scope, so it has to run the scope's
+      // jobs itself.
+      let sum = pool.spawn(move || {
+         let numbers = [1, 2, 3, 4];
+
+         inner.scope(|s| {
+            let handles: Vec<_> = numbers.iter().map(|n| s.spawn(move || n * 2)).collect();
+
+            handles.into_iter().map(|handle| handle.join().unwrap()).sum::<i32>()
+         })
+      });
+
+      assert_eq!(20, sum.join().unwrap());
+   }
+}
@@ -0,0 +1,467 @@
+/* System includes */
+use std::fmt;
+use std::mem;
+use std::thread;
+
+use std::cmp::{Ordering as Order, Reverse};
+use std::collections::BinaryHeap;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+use std::time::{Duration, Instant};
+
+/* extern create usings */
+use super::{execute, lock, Priority, Shared};
+
+/** The timers not yet due, soonest first, and the thread firing them. */
+#[derive(Default)]
+pub(super) struct Timers {
+   heap: BinaryHeap<Reverse<Timer>>,
+   /// Timers cancelled since the heap was last rebuilt. They stay in the
+   /// heap until due, unless they come to outnumber the rest.
+   cancelled: usize,
+   /// Orders timers due at the same instant by when they were scheduled.
+   next_seq: u64,
+   thread: Option<thread::JoinHandle<()>>,
+}
+
+struct Timer {
+   due: Instant,
+   seq: u64,
+   task: Arc<Task>,
+}
+
+/** A scheduled job, shared by its timer, its runs and its handle. */
+struct Task {
+   job: Mutex<Box<dyn FnMut() + Send>>,
+   period: Option<Duration>,
+   cancelled: AtomicBool,
+   /// Set while a run is queued or running, so that a slow periodic job
+   /// skips ticks rather than piling up runs.
+   running: AtomicBool,
+}
+
+struct Running(Arc<Task>);
+
+/** Cancels a job scheduled with `execute_after` or `execute_every`.
+ *
+ * Dropping the handle does not cancel the job.
+ */
+#[derive(Clone)]
+pub struct TimerHandle {
+   task: Arc<Task>,
+   /// Where to count the cancelled timer, unless the pool is gone.
+   shared: Weak<Shared>,
+}
+
+pub(super) fn execute_after<F>(shared: &Arc<Shared>, delay: Duration, f: F) -> TimerHandle
+   where
+      F: FnOnce() + Send + 'static
+{
+   let mut f = Some(f);
+
+   schedule(shared, delay, None, Box::new(move || {
+      if let Some(f) = f.take() {
+         f();
+      }
+   }))
+}
+
+pub(super) fn execute_every<F>(shared: &Arc<Shared>, period: Duration, f: F) -> TimerHandle
+   where
+      F: FnMut() + Send + 'static
+{
+   assert!(period > Duration::from_secs(0));
+
+   schedule(shared, period, Some(period), Box::new(f))
+}
+
+/// Adds a timer due after `delay`, starting the timer thread if this is the
+/// first. Once the pool is dropped, the handle comes back cancelled.
+fn schedule(shared: &Arc<Shared>, delay: Duration, period: Option<Duration>, job: Box<dyn FnMut() + Send>) -> TimerHandle {
+   let task = Arc::new(Task {
+      job: Mutex::new(job),
+      period,
+      cancelled: AtomicBool::new(false),
+      running: AtomicBool::new(false),
+   });
+
+   let mut timers = lock(&shared.timers);
+
+   // Checked under the timers lock, as the pool's drop takes the thread.
+   if shared.shutdown.load(Ordering::SeqCst) {
+      task.cancelled.store(true, Ordering::SeqCst);
+
+      return TimerHandle {
+         task,
+         shared: Weak::new(),
+      };
+   }
+
+   if timers.thread.is_none() {
+      let mut builder = thread::Builder::new();
+
+      if let Some(ref name) = shared.config.thread_name {
+         builder = builder.name(format!("{}-timer", name));
+      }
+
+      let timer_shared = Arc::clone(shared);
+
+      match builder.spawn(move || run_timers(timer_shared)) {
+         Ok(thread) => timers.thread = Some(thread),
+         Err(e) => panic!("Failed to start the timer thread: {}", e),
+      }
+   }
+
+   let seq = timers.next_seq;
+
+   timers.next_seq += 1;
+
+   let due = Instant::now() + delay;
+   let wake = timers.heap.peek().is_none_or(|Reverse(soonest)| due < soonest.due);
+
+   timers.heap.push(Reverse(Timer {
+      due,
+      seq,
+      task: Arc::clone(&task),
+   }));
+
+   if wake {
+      shared.timer_wake.notify_one();
+   }
+
+   TimerHandle {
+      task,
+      shared: Arc::downgrade(shared),
+   }
+}
+
+/// The timer thread: sleeps until the soonest timer is due, submits its job
+/// and, for a periodic timer, schedules the next run.
+fn run_timers(shared: Arc<Shared>) {
+   let mut timers = lock(&shared.timers);
+
+   while !shared.shutdown.load(Ordering::SeqCst) {
+      let now = Instant::now();
+
+      let due = match timers.heap.peek() {
+         Some(Reverse(timer)) => timer.due,
+         None => {
+            timers = shared.timer_wake.wait(timers).unwrap_or_else(PoisonError::into_inner);
+
+            continue;
+         },
+      };
+
+      if due > now {
+         timers = shared.timer_wake.wait_timeout(timers, due - now).unwrap_or_else(PoisonError::into_inner).0;
+
+         continue;
+      }
+
+      let Reverse(timer) = timers.heap.pop().expect("the soonest timer has gone");
+
+      if timer.task.cancelled.load(Ordering::SeqCst) {
+         timers.cancelled = timers.cancelled.saturating_sub(1);
+
+         continue;
+      }
+
+      if let Some(period) = timer.task.period {
+         // Ticks missed while the thread was held up are skipped, not
+         // fired in a burst.
+         let mut next = timer.due + period;
+
+         if next <= now {
+            next = now + period;
+         }
+
+         let seq = timers.next_seq;
+
+         timers.next_seq += 1;
+         timers.heap.push(Reverse(Timer {
+            due: next,
+            seq,
+            task: Arc::clone(&timer.task),
+         }));
+      }
+
+      // Submitted without the lock, as a full queue may block.
+      drop(timers);
+      fire(&shared, timer.task);
+      timers = lock(&shared.timers);
+   }
+}
+
+/// Submits a run of a timer's job, unless the last run has not finished.
+fn fire(shared: &Arc<Shared>, task: Arc<Task>) {
+   if task.running.swap(true, Ordering::SeqCst) {
+      return;
+   }
+
+   // Clears `running` however the run ends: finished, panicked, or
+   // dropped because the queue was full.
+   let run = Running(task);
+
+   execute(shared, Priority::Normal, move || {
+      let task = &run.0;
+
+      if !task.cancelled.load(Ordering::SeqCst) {
+         (*lock(&task.job))();
+      }
+   });
+}
+
+impl TimerHandle {
+   /// Stops the job from running again. A run already submitted to the
+   /// pool is skipped if it has not started.
+   pub fn cancel(&self) {
+      if self.task.cancelled.swap(true, Ordering::SeqCst) {
+         return;
+      }
+
+      if let Some(shared) = self.shared.upgrade() {
+         lock(&shared.timers).count_cancelled();
+      }
+   }
+
+   /// Returns whether the job has been cancelled.
+   pub fn is_cancelled(&self) -> bool {
+      self.task.cancelled.load(Ordering::SeqCst)
+   }
+}
+
+impl fmt::Debug for TimerHandle {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      f.debug_struct("TimerHandle")
+         .field("period", &self.task.period)
+         .field("cancelled", &self.is_cancelled())
+         .finish()
+   }
+}
+
+impl Timers {
+   /// Counts a newly cancelled timer, and once cancelled timers are more
+   /// than half the heap, rebuilds it without them. A one-off timer that
+   /// has already fired is counted too, which only brings the rebuild
+   /// forward.
+   fn count_cancelled(&mut self) {
+      self.cancelled += 1;
+
+      if self.cancelled > self.heap.len() / 2 {
+         self.heap.retain(|Reverse(timer)| !timer.task.cancelled.load(Ordering::SeqCst));
+         self.cancelled = 0;
+      }
+   }
+}
+
+impl Drop for Running {
+   fn drop(&mut self) {
+      self.0.running.store(false, Ordering::SeqCst);
+   }
+}
+
+impl Ord for Timer {
+   fn cmp(&self, other: &Timer) -> Order {
+      (self.due, self.seq).cmp(&(other.due, other.seq))
+   }
+}
+
+impl PartialOrd for Timer {
+   fn partial_cmp(&self, other: &Timer) -> Option<Order> {
+      Some(self.cmp(other))
+   }
+}
+
+impl PartialEq for Timer {
+   fn eq(&self, other: &Timer) -> bool {
+      self.cmp(other) == Order::Equal
+   }
+}
+
+impl Eq for Timer {}
+
+impl Shared {
+   /// Stops the timer thread. Timers not yet due never fire; their jobs are
+   /// dropped here rather than with the last Spawner.
+   pub(super) fn stop_timers(&self) {
+      let (timers, thread) = {
+         let mut timers = lock(&self.timers);
+
+         self.timer_wake.notify_all();
+
+         (mem::take(&mut timers.heap), timers.thread.take())
+      };
+
+      drop(timers);
+
+      if let Some(thread) = thread {
+         thread.join().unwrap();
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use pool::tests::eventually;
+   use pool::ThreadPool;
+
+   use std::sync::atomic::AtomicUsize;
+   use std::sync::mpsc;
+
+   #[test]
+   fn execute_after_waits_for_the_delay() {
+      let pool = ThreadPool::new(2);
+      let (sender, receiver) = mpsc::channel();
+      let start = Instant::now();
+
+      pool.execute_after(Duration::from_millis(100), move || sender.send(Instant::now()).unwrap());
+
+      let ran = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+      assert!(ran - start >= Duration::from_millis(100));
+   }
+
+   #[test]
+   fn timers_fire_in_order_of_due_time() {
+      let pool = ThreadPool::new(1);
+      let (sender, receiver) = mpsc::channel();
+
+      for &delay in &[60, 20, 40, 0] {
+         let sender = sender.clone();
+
+         pool.execute_after(Duration::from_millis(delay), move || sender.send(delay).unwrap());
+      }
+
+      let order: Vec<u64> = (0..4).map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+
+      assert_eq!(vec![0, 20, 40, 60], order);
+   }
+
+   #[test]
+   fn cancelled_timers_never_run() {
+      let pool = ThreadPool::new(1);
+      let ran = Arc::new(AtomicBool::new(false));
+      let flag = Arc::clone(&ran);
+
+      let handle = pool.execute_after(Duration::from_millis(50), move || flag.store(true, Ordering::SeqCst));
+
+      handle.cancel();
+      assert!(handle.is_cancelled());
+
+      thread::sleep(Duration::from_millis(150));
+
+      assert!(!ran.load(Ordering::SeqCst));
+   }
+
+   #[test]
+   fn cancelled_timers_leave_the_heap() {
+      let pool = ThreadPool::new(1);
+      let handles: Vec<TimerHandle> = (0..100).map(|_| pool.execute_after(Duration::from_secs(60), || ())).collect();
+
+      assert_eq!(100, lock(&pool.shared.timers).heap.len());
+
+      for handle in &handles[..50] {
+         handle.cancel();
+         handle.cancel();
+      }
+
+      // Cancelled timers stay while they are no more than half the heap.
+      assert_eq!(100, lock(&pool.shared.timers).heap.len());
+
+      handles[50].cancel();
+
+      assert_eq!(49, lock(&pool.shared.timers).heap.len());
+      assert_eq!(0, lock(&pool.shared.timers).cancelled);
+
+      for handle in &handles {
+         handle.cancel();
+      }
+
+      assert_eq!(0, lock(&pool.shared.timers).heap.len());
+   }
+
+   #[test]
+   fn execute_every_repeats_until_cancelled() {
+      let pool = ThreadPool::new(2);
+      let ticks = Arc::new(AtomicUsize::new(0));
+      let counter = Arc::clone(&ticks);
+
+      let handle = pool.execute_every(Duration::from_millis(10), move || {
+         counter.fetch_add(1, Ordering::SeqCst);
+      });
+
+      eventually(|| ticks.load(Ordering::SeqCst) >= 5);
+      handle.cancel();
+
+      // A run may have been submitted just before the cancel.
+      thread::sleep(Duration::from_millis(50));
+
+      let stopped = ticks.load(Ordering::SeqCst);
+
+      thread::sleep(Duration::from_millis(50));
+
+      assert_eq!(stopped, ticks.load(Ordering::SeqCst));
+   }
+
+   #[test]
+   fn slow_periodic_jobs_skip_ticks() {
+      let pool = ThreadPool::new(4);
+      let running = Arc::new(AtomicUsize::new(0));
+      let overlapped = Arc::new(AtomicBool::new(false));
+      let (running2, overlapped2) = (Arc::clone(&running), Arc::clone(&overlapped));
+
+      let handle = pool.execute_every(Duration::from_millis(5), move || {
+         if running2.fetch_add(1, Ordering::SeqCst) > 0 {
+            overlapped2.store(true, Ordering::SeqCst);
+         }
+
+         thread::sleep(Duration::from_millis(30));
+         running2.fetch_sub(1, Ordering::SeqCst);
+      });
+
+      thread::sleep(Duration::from_millis(200));
+      handle.cancel();
+
+      assert!(!overlapped.load(Ordering::SeqCst));
+   }
+
+   #[test]
+   fn many_timers_share_one_thread() {
+      let pool = ThreadPool::builder().min_threads(2).max_threads(2).thread_name("many").build();
+      let (sender, receiver) = mpsc::channel();
+
+      for i in 0..2000 {
+         let sender = sender.clone();
+
+         pool.execute_after(Duration::from_millis(i % 50), move || {
+            sender.send(thread::current().name().map(String::from)).unwrap();
+         });
+      }
+
+      for _ in 0..2000 {
+         let name = receiver.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+
+         assert!(name == "many-0" || name == "many-1");
+      }
+   }
+
+   #[test]
+   fn drop_discards_pending_timers() {
+      let pool = ThreadPool::new(1);
+      let spawner = pool.spawner();
+      let ran = Arc::new(AtomicBool::new(false));
+      let flag = Arc::clone(&ran);
+
+      pool.execute_after(Duration::from_millis(50), move || flag.store(true, Ordering::SeqCst));
+      drop(pool);
+
+      thread::sleep(Duration::from_millis(100));
+
+      assert!(!ran.load(Ordering::SeqCst));
+      assert!(spawner.execute_after(Duration::from_millis(0), || ()).is_cancelled());
+   }
+}
@@ -0,0 +1,788 @@
+/* extern create usings */
+use http::{self, ParseError, Request, Response, MAX_BODY, MAX_HEAD};
+use router::Router;
+use server::{self, ServerOptions, Shutdown, Summary};
+use {RejectionPolicy, ThreadPool};
+
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token, Waker};
+
+/* System includes */
+use std::io;
+use std::net;
+use std::panic;
+
+use std::collections::HashMap;
+use std::io::prelude::*;
+
+use std::net::{Shutdown as Close, SocketAddr};
+
+use std::panic::AssertUnwindSafe;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+use std::time::{Duration, Instant};
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+
+/// How often connections are checked for having idled too long.
+const SWEEP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How much of a client's input to hold while its request is with the pool.
+const MAX_INPUT: usize = MAX_HEAD + MAX_BODY;
+
+/** Serves connections like `server::Server`, but from one thread that
+ * waits on all of them at once with epoll (kqueue on the BSDs), so an idle
+ * or slow client costs a buffer rather than a thread. Only complete
+ * requests go to the ThreadPool, whose threads run the router's handlers.
+ *
+ * A handler that blocks still holds a pool thread, though one that only
+ * needs its response sent later can say so with `Response::delay`, which
+ * the pool's timer waits out instead. With every thread busy, further
+ * requests queue, and beyond `queue_capacity` are answered with 503 Service
+ * Unavailable.
+ */
+pub struct Server {
+   listener: TcpListener,
+   poll: Poll,
+   router: Arc<Router>,
+   options: ServerOptions,
+   stopping: Arc<AtomicBool>,
+}
+
+/** What `Server::run` keeps between events. */
+struct Reactor {
+   poll: Poll,
+   pool: ThreadPool,
+   router: Arc<Router>,
+   options: ServerOptions,
+   stopping: Arc<AtomicBool>,
+   waker: Arc<Waker>,
+   /// Where pool threads send the responses they make.
+   answers: Sender<Answer>,
+   answered: Receiver<Answer>,
+   connections: HashMap<Token, Connection>,
+   next_token: usize,
+   accepted: usize,
+   requests: usize,
+   rejected: usize,
+}
+
+/** A client, and where its requests and responses are up to. */
+struct Connection {
+   stream: TcpStream,
+   /// Bytes received and not yet parsed.
+   input: Vec<u8>,
+   /// Bytes of responses not yet written.
+   output: Vec<u8>,
+   served: usize,
+   /// Whether a request is with the pool. Requests are answered in order,
+   /// so the next one waits until then.
+   busy: bool,
+   /// Whether to close once `output` is written.
+   closing: bool,
+   /// Whether the client has shut its side down.
+   eof: bool,
+   idle_since: Instant,
+   /// When the first byte of the request being received arrived.
+   request_since: Option<Instant>,
+}
+
+/** A request the pool has answered, for the connection `token`. */
+struct Answer {
+   token: Token,
+   request: Request,
+   response: Response,
+}
+
+impl Server {
+   /// Create a new Server.
+   ///
+   /// ## Input:
+   ///
+   /// * `listener` - Where to accept connections.
+   /// * `router` - Answers each request.
+   /// * `options` - How to run the server.
+   ///
+   /// ## Returns
+   ///
+   /// * `Err(_)` - The options ask for TLS, which only `server::Server`
+   ///   serves, the listener cannot be made non-blocking, or the poller
+   ///   cannot be created.
+   pub fn new(listener: net::TcpListener, router: Router, options: ServerOptions) -> io::Result<Server> {
+      if options.tls.is_some() {
+         return Err(io::Error::new(io::ErrorKind::InvalidInput, "the reactor does not serve TLS"));
+      }
+
+      listener.set_nonblocking(true)?;
+
+      Ok(Server {
+         listener: TcpListener::from_std(listener),
+         poll: Poll::new()?,
+         router: Arc::new(router),
+         options,
+         stopping: Arc::new(AtomicBool::new(false)),
+      })
+   }
+
+   pub fn local_addr(&self) -> io::Result<SocketAddr> {
+      self.listener.local_addr()
+   }
+
+   /// Returns a handle to shut the server down with.
+   pub fn shutdown_handle(&self) -> io::Result<Shutdown> {
+      Ok(Shutdown::new(Arc::clone(&self.stopping), self.local_addr()?))
+   }
+
+   /// Answers connections until shut down, then closes the listener and
+   /// the idle connections, and gives requests in progress up to
+   /// `drain_timeout` to finish, as `server::Server::run` does.
+   ///
+   /// ## Panics
+   ///
+   /// * The 'run' function will panic if the poller fails.
+   pub fn run(self) -> Summary {
+      let started = Instant::now();
+      let Server { mut listener, poll, router, options, stopping } = self;
+
+      let pool = ThreadPool::builder()
+         .min_threads(options.threads)
+         .max_threads(options.threads)
+         .queue_capacity(options.queue_capacity)
+         .rejection_policy(RejectionPolicy::Reject)
+         .build();
+
+      let stats = options.stats_interval.map(|interval| {
+         let spawner = pool.spawner();
+
+         pool.execute_every(interval, move || info!("{}", spawner.metrics()))
+      });
+
+      let waker = Waker::new(poll.registry(), WAKER).expect("Failed to create a waker");
+      let (answers, answered) = mpsc::channel();
+
+      poll.registry().register(&mut listener, LISTENER, Interest::READABLE).expect("Failed to poll the listener");
+
+      let mut reactor = Reactor {
+         poll,
+         pool,
+         router,
+         options,
+         stopping,
+         waker: Arc::new(waker),
+         answers,
+         answered,
+         connections: HashMap::new(),
+         next_token: WAKER.0 + 1,
+         accepted: 0,
+         requests: 0,
+         rejected: 0,
+      };
+
+      let mut listener = Some(listener);
+      let mut events = Events::with_capacity(1024);
+      let mut deadline = None;
+      let mut next_sweep = Instant::now() + SWEEP_INTERVAL;
+
+      loop {
+         if let Err(e) = reactor.poll.poll(&mut events, Some(SWEEP_INTERVAL)) {
+            if e.kind() != io::ErrorKind::Interrupted {
+               panic!("Failed to poll: {}", e);
+            }
+         }
+
+         for event in events.iter() {
+            match event.token() {
+               LISTENER => {
+                  if let Some(ref listener) = listener {
+                     reactor.accept(listener);
+                  }
+               },
+               WAKER => reactor.answer(),
+               token => reactor.drive(token),
+            }
+         }
+
+         let now = Instant::now();
+
+         if deadline.is_none() && reactor.stopping.load(Ordering::SeqCst) {
+            info!("Stopped accepting connections; draining.");
+
+            if let Some(ref stats) = stats {
+               stats.cancel();
+            }
+
+            // Closing the listener refuses further connections.
+            if let Some(mut listener) = listener.take() {
+               let _ = reactor.poll.registry().deregister(&mut listener);
+            }
+
+            deadline = Some(now + reactor.options.drain_timeout);
+            next_sweep = now;
+         }
+
+         if now >= next_sweep {
+            // Edge-triggered, the listener is not polled again for
+            // connections left behind by a failed accept.
+            if let Some(ref listener) = listener {
+               reactor.accept(listener);
+            }
+
+            reactor.sweep(now);
+            next_sweep = now + SWEEP_INTERVAL;
+         }
+
+         match deadline {
+            Some(_) if reactor.connections.is_empty() => break,
+            Some(deadline) if now >= deadline => break,
+            _ => {},
+         }
+      }
+
+      reactor.finish(started)
+   }
+}
+
+impl Reactor {
+   /// Accepts every connection waiting on the listener.
+   fn accept(&mut self, listener: &TcpListener) {
+      loop {
+         let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+            Err(e) => {
+               error!("Failed to accept a connection: {}", e);
+
+               return;
+            },
+         };
+
+         if self.stopping.load(Ordering::SeqCst) {
+            // Most likely the shutdown handle waking us.
+            continue;
+         }
+
+         let token = Token(self.next_token);
+
+         self.next_token += 1;
+
+         if let Err(e) = self.poll.registry().register(&mut stream, token, Interest::READABLE | Interest::WRITABLE) {
+            error!("Failed to poll a connection: {}", e);
+
+            continue;
+         }
+
+         self.accepted += 1;
+         self.connections.insert(token, Connection {
+            stream,
+            input: Vec::new(),
+            output: Vec::new(),
+            served: 0,
+            busy: false,
+            closing: false,
+            eof: false,
+            idle_since: Instant::now(),
+            request_since: None,
+         });
+
+         // The client may have sent its request before we registered.
+         self.drive(token);
+      }
+   }
+
+   /// Writes the responses the pool has finished.
+   fn answer(&mut self) {
+      while let Ok(Answer { token, request, response }) = self.answered.try_recv() {
+         self.requests += 1;
+
+         let connection = match self.connections.get_mut(&token) {
+            Some(connection) => connection,
+            None => continue,
+         };
+
+         let (response, keep_alive) = server::keep_alive(
+            &request,
+            response,
+            connection.served,
+            &self.options.connection,
+            &self.stopping
+         );
+
+         // Writing to a Vec cannot fail.
+         let _ = response.write_to(&mut connection.output);
+
+         connection.busy = false;
+         connection.closing = !keep_alive;
+         connection.idle_since = Instant::now();
+
+         self.drive(token);
+      }
+   }
+
+   /// Reads, parses, dispatches and writes whatever connection `token` is
+   /// ready for, and closes it once it is done. A panic on the way closes
+   /// just this connection rather than ending the loop serving the others.
+   fn drive(&mut self, token: Token) {
+      if panic::catch_unwind(AssertUnwindSafe(|| self.step(token))).is_err() {
+         error!("Panicked serving a connection; closing it.");
+
+         self.close(token);
+      }
+   }
+
+   fn step(&mut self, token: Token) {
+      let done = match self.connections.get_mut(&token) {
+         Some(connection) => {
+            connection.fill();
+
+            if !connection.busy && !connection.closing {
+               match http::parse(&connection.input) {
+                  Ok(Some((request, len))) => {
+                     connection.input.drain(..len);
+                     connection.served += 1;
+                     connection.busy = true;
+                     connection.request_since = None;
+
+                     Some(request)
+                  },
+                  Ok(None) => {
+                     if !connection.input.is_empty() {
+                        connection.request_since.get_or_insert_with(Instant::now);
+                     }
+
+                     None
+                  },
+                  Err(e) => {
+                     match e.response() {
+                        Some(response) => { let _ = response.write_to(&mut connection.output); },
+                        None => debug!("Dropping connection: {}", e),
+                     }
+
+                     connection.closing = true;
+
+                     None
+                  },
+               }
+            } else {
+               None
+            }
+         },
+         None => return,
+      };
+
+      if let Some(request) = done {
+         self.dispatch(token, request);
+      }
+
+      let close = match self.connections.get_mut(&token) {
+         Some(connection) => {
+            let flushed = connection.flush();
+
+            !connection.busy && (!flushed || (connection.output.is_empty() && (connection.closing || connection.eof)))
+         },
+         None => return,
+      };
+
+      if close {
+         self.close(token);
+      }
+   }
+
+   /// Hands a request to the pool, or answers 503 if its queue is full.
+   fn dispatch(&mut self, token: Token, request: Request) {
+      let router = Arc::clone(&self.router);
+      let answers = self.answers.clone();
+      let waker = Arc::clone(&self.waker);
+      let spawner = self.pool.spawner();
+
+      let job = self.pool.try_execute(move || {
+         let mut response = panic::catch_unwind(AssertUnwindSafe(|| router.handle(&request)))
+            .unwrap_or_else(|_| Response::new(500).header("Connection", "close"));
+
+         let delay = response.delay.take();
+
+         let answer = move || {
+            // Nobody is listening once the server has given up draining.
+            if answers.send(Answer { token, request, response }).is_ok() {
+               let _ = waker.wake();
+            }
+         };
+
+         // A delayed response waits on the timer, not on this thread.
+         match delay {
+            Some(delay) => { spawner.execute_after(delay, answer); },
+            None => answer(),
+         }
+      });
+
+      if job.is_err() {
+         self.rejected += 1;
+
+         if let Some(connection) = self.connections.get_mut(&token) {
+            let _ = server::busy().write_to(&mut connection.output);
+
+            connection.busy = false;
+            connection.closing = true;
+         }
+      }
+   }
+
+   /// Closes connections idle for longer than `idle_timeout`, or at all
+   /// once the server is stopping, and answers 408 to requests that have
+   /// taken longer than `request_timeout` to arrive.
+   fn sweep(&mut self, now: Instant) {
+      let stopping = self.stopping.load(Ordering::SeqCst);
+      let idle_timeout = self.options.connection.idle_timeout;
+      let request_timeout = self.options.connection.request_timeout;
+
+      let expired: Vec<Token> = self.connections
+         .iter()
+         .filter(|&(_, connection)| {
+            let idle = !connection.busy && connection.output.is_empty() && connection.input.is_empty();
+
+            idle && (stopping || now - connection.idle_since >= idle_timeout)
+         })
+         .map(|(&token, _)| token)
+         .collect();
+
+      for token in expired {
+         self.close(token);
+      }
+
+      let late: Vec<Token> = self.connections
+         .iter()
+         .filter(|&(_, connection)| {
+            !connection.busy && !connection.closing
+               && connection.request_since.is_some_and(|since| now - since >= request_timeout)
+         })
+         .map(|(&token, _)| token)
+         .collect();
+
+      for token in late {
+         if let Some(connection) = self.connections.get_mut(&token) {
+            if let Some(response) = ParseError::Timeout.response() {
+               let _ = response.write_to(&mut connection.output);
+            }
+
+            connection.closing = true;
+         }
+
+         self.drive(token);
+      }
+   }
+
+   fn close(&mut self, token: Token) {
+      if let Some(mut connection) = self.connections.remove(&token) {
+         let _ = self.poll.registry().deregister(&mut connection.stream);
+
+         connection.close();
+      }
+   }
+
+   /// Counts what is left, and joins the pool, or detaches it if requests
+   /// are still running on it.
+   fn finish(self, started: Instant) -> Summary {
+      let abandoned = self.connections.len();
+      let busy = self.connections.values().any(|connection| connection.busy);
+
+      if busy {
+         self.pool.detach();
+      } else {
+         drop(self.pool);
+      }
+
+      Summary {
+         connections: self.accepted,
+         requests: self.requests,
+         rejected: self.rejected,
+         abandoned,
+         uptime: started.elapsed(),
+      }
+   }
+}
+
+impl Connection {
+   /// Reads what the client has sent, unless enough is already waiting.
+   fn fill(&mut self) {
+      let mut buf = [0; 4096];
+
+      while !self.eof && self.input.len() < MAX_INPUT {
+         match self.stream.read(&mut buf) {
+            Ok(0) => self.eof = true,
+            Ok(read) => self.input.extend_from_slice(&buf[..read]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+            Err(e) => {
+               debug!("Dropping connection: {}", e);
+
+               self.eof = true;
+               self.closing = true;
+            },
+         }
+      }
+   }
+
+   /// Writes as much of the output as the socket will take.
+   ///
+   /// ## Returns
+   ///
+   /// * `false` - The connection is broken.
+   fn flush(&mut self) -> bool {
+      while !self.output.is_empty() {
+         match self.stream.write(&self.output) {
+            Ok(0) => return false,
+            Ok(written) => { self.output.drain(..written); },
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+            Err(e) => {
+               debug!("Failed to write response: {}", e);
+
+               return false;
+            },
+         }
+      }
+
+      true
+   }
+
+   /// Closes the connection, reading whatever has arrived first, as
+   /// `server::discard_input` explains.
+   fn close(mut self) {
+      let _ = self.stream.shutdown(Close::Write);
+
+      server::discard_input(&mut self.stream);
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use server::ConnectionOptions;
+   use test_support::{self, get, Started};
+
+   use std::net::TcpStream;
+   use std::thread;
+
+   fn start(threads: usize, slow: Duration, drain_timeout: Duration) -> Started {
+      let options = ServerOptions {
+         threads,
+         queue_capacity: 1,
+         drain_timeout,
+         ..ServerOptions::default()
+      };
+
+      test_support::start::<Server>(slow, options)
+   }
+
+   #[test]
+   fn serves_pipelined_and_keep_alive_requests() {
+      let (address, shutdown, _, server) = start(2, Duration::from_secs(0), Duration::from_secs(5));
+
+      let mut client = TcpStream::connect(address).unwrap();
+      let mut output = String::new();
+
+      client.write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n").unwrap();
+      thread::sleep(Duration::from_millis(50));
+      client.write_all(b"\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+      client.read_to_string(&mut output).unwrap();
+
+      assert_eq!(3, output.matches("hello").count());
+      assert!(output.ends_with("Connection: close\r\nContent-Length: 5\r\n\r\nhello"));
+
+      shutdown.trigger();
+
+      let summary = server.join().unwrap();
+
+      assert_eq!((1, 3, 0), (summary.connections, summary.requests, summary.abandoned));
+   }
+
+   #[test]
+   fn idle_connections_do_not_hold_threads() {
+      let (address, shutdown, _, server) = start(1, Duration::from_secs(0), Duration::from_secs(5));
+
+      // Each of these would take the only thread of a `server::Server`.
+      let mut idle: Vec<TcpStream> = (0..50).map(|_| TcpStream::connect(address).unwrap()).collect();
+
+      assert!(get(address, "/").ends_with("hello"));
+
+      shutdown.trigger();
+
+      let summary = server.join().unwrap();
+
+      assert_eq!((51, 0), (summary.connections, summary.abandoned));
+      assert_eq!(0, idle[0].read(&mut [0; 16]).unwrap());
+   }
+
+   #[test]
+   fn delayed_responses_do_not_hold_threads() {
+      let options = ServerOptions {
+         threads: 1,
+         ..ServerOptions::default()
+      };
+
+      let (address, shutdown, _, server) = test_support::start::<Server>(Duration::from_millis(500), options);
+      let start = Instant::now();
+
+      let mut delayed = TcpStream::connect(address).unwrap();
+      let mut output = String::new();
+
+      delayed.write_all(b"GET /delayed HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+
+      // The only thread is free while the delayed response waits.
+      assert!(get(address, "/").ends_with("hello"));
+      assert!(start.elapsed() < Duration::from_millis(500));
+
+      delayed.read_to_string(&mut output).unwrap();
+
+      assert!(output.ends_with("delayed"));
+      assert!(start.elapsed() >= Duration::from_millis(500));
+
+      shutdown.trigger();
+      server.join().unwrap();
+   }
+
+   #[test]
+   fn malformed_requests_and_panics_are_answered() {
+      let (address, shutdown, _, server) = start(1, Duration::from_secs(0), Duration::from_secs(5));
+
+      let mut client = TcpStream::connect(address).unwrap();
+      let mut output = String::new();
+
+      client.write_all(b"BAD\r\n\r\n").unwrap();
+      client.read_to_string(&mut output).unwrap();
+
+      assert!(output.starts_with("HTTP/1.1 400 Bad Request"));
+      assert!(get(address, "/panic").starts_with("HTTP/1.1 500 Internal Server Error"));
+      assert!(get(address, "/").ends_with("hello"));
+
+      shutdown.trigger();
+      server.join().unwrap();
+   }
+
+   #[test]
+   fn overflowing_chunk_sizes_leave_the_loop_running() {
+      let (address, shutdown, _, server) = start(1, Duration::from_secs(0), Duration::from_secs(5));
+
+      let mut client = TcpStream::connect(address).unwrap();
+      let mut output = String::new();
+
+      client
+         .write_all(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\nffffffffffffffff\r\n")
+         .unwrap();
+      client.read_to_string(&mut output).unwrap();
+
+      assert!(output.starts_with("HTTP/1.1 413"));
+      assert!(get(address, "/").ends_with("hello"));
+
+      shutdown.trigger();
+      server.join().unwrap();
+   }
+
+   #[test]
+   fn incomplete_requests_time_out() {
+      let mut router = Router::new();
+
+      router.post("/echo", |request, _| Response::new(200).body(request.body.clone()));
+
+      let options = ServerOptions {
+         threads: 1,
+         connection: ConnectionOptions {
+            idle_timeout: Duration::from_millis(50),
+            request_timeout: Duration::from_millis(300),
+            ..ConnectionOptions::default()
+         },
+         ..ServerOptions::default()
+      };
+
+      let server = Server::new(net::TcpListener::bind("127.0.0.1:0").unwrap(), router, options).unwrap();
+      let address = server.local_addr().unwrap();
+      let shutdown = server.shutdown_handle().unwrap();
+      let server = thread::spawn(move || server.run());
+
+      // A body arriving over longer than the idle timeout is still read.
+      let mut client = TcpStream::connect(address).unwrap();
+      let mut output = String::new();
+
+      client.write_all(b"POST /echo HTTP/1.1\r\nConnection: close\r\nContent-Length: 4\r\n\r\nab").unwrap();
+      thread::sleep(Duration::from_millis(150));
+      client.write_all(b"cd").unwrap();
+      client.read_to_string(&mut output).unwrap();
+
+      assert!(output.ends_with("\r\n\r\nabcd"));
+
+      // One that is never finished is answered 408.
+      let mut client = TcpStream::connect(address).unwrap();
+      let mut output = String::new();
+
+      client.write_all(b"POST /echo HTTP/1.1\r\nContent-Length: 4\r\n\r\nab").unwrap();
+      client.read_to_string(&mut output).unwrap();
+
+      assert!(output.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+
+      shutdown.trigger();
+      server.join().unwrap();
+   }
+
+   #[test]
+   fn full_queue_answers_503() {
+      let (address, shutdown, started, server) = start(1, Duration::from_millis(300), Duration::from_secs(5));
+
+      let slow = thread::spawn(move || get(address, "/slow"));
+
+      started.recv().unwrap();
+
+      // One waits in the queue; the next is turned away.
+      let queued = thread::spawn(move || get(address, "/"));
+
+      thread::sleep(Duration::from_millis(50));
+
+      let rejected = get(address, "/");
+
+      assert!(rejected.starts_with("HTTP/1.1 503 Service Unavailable"));
+      assert!(slow.join().unwrap().ends_with("slow"));
+      assert!(queued.join().unwrap().ends_with("hello"));
+
+      shutdown.trigger();
+
+      assert_eq!(1, server.join().unwrap().rejected);
+   }
+
+   #[test]
+   fn shutdown_finishes_requests_in_progress() {
+      let (address, shutdown, started, server) = start(2, Duration::from_millis(300), Duration::from_secs(5));
+
+      let mut idle = TcpStream::connect(address).unwrap();
+      let slow = thread::spawn(move || get(address, "/slow"));
+
+      started.recv().unwrap();
+      shutdown.trigger();
+
+      let output = slow.join().unwrap();
+
+      assert!(output.contains("Connection: close"));
+      assert!(output.ends_with("slow"));
+      assert_eq!(0, idle.read(&mut [0; 16]).unwrap());
+      assert_eq!(0, server.join().unwrap().abandoned);
+      assert!(TcpStream::connect_timeout(&address, Duration::from_secs(1)).is_err());
+   }
+
+   #[test]
+   fn drain_timeout_abandons_slow_requests() {
+      let (address, shutdown, started, server) = start(1, Duration::from_secs(2), Duration::from_millis(100));
+
+      let _slow = thread::spawn(move || get(address, "/slow"));
+
+      started.recv().unwrap();
+
+      let start = Instant::now();
+
+      shutdown.trigger();
+
+      assert_eq!(1, server.join().unwrap().abandoned);
+      assert!(start.elapsed() < Duration::from_secs(1));
+   }
+}
@@ -0,0 +1,303 @@
+/* extern create usings */
+use http::{Request, Response};
+
+/** Parameters extracted from the path by a route pattern. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params {
+   params: Vec<(String, String)>,
+}
+
+impl Params {
+   /// Returns the value captured by `:name` or `*name`.
+   pub fn get(&self, name: &str) -> Option<&str> {
+      self.params.iter()
+         .find(|(param, _)| param == name)
+         .map(|(_, value)| value.as_str())
+   }
+}
+
+/// A function answering a request.
+pub type Handler = Box<dyn Fn(&Request, &Params) -> Response + Send + Sync>;
+
+enum Segment {
+   Literal(String),
+   Param(String),
+   Wildcard(String),
+}
+
+struct Route {
+   method: String,
+   segments: Vec<Segment>,
+   handler: Handler,
+}
+
+/** Dispatches requests to handlers by method and path. */
+pub struct Router {
+   routes: Vec<Route>,
+   not_found: Handler,
+}
+
+impl Router {
+   /// Create a new Router with no routes, answering everything with 404.
+   pub fn new() -> Router {
+      Router {
+         routes: Vec::new(),
+         not_found: Box::new(|_: &Request, _: &Params| {
+            Response::new(404)
+               .header("Content-Type", "text/plain; charset=utf-8")
+               .body("Not found\n")
+         }),
+      }
+   }
+
+   /// Registers a handler.
+   ///
+   /// ## Input:
+   ///
+   /// * `method` - The request method the route answers.
+   /// * `pattern` - The path the route answers. A segment `:name` matches
+   ///   any single segment, and a final segment `*name` (or just `*`)
+   ///   matches the rest of the path, which may be empty.
+   /// * `handler` - Called with the request and the captured parameters.
+   ///
+   /// ## Panics
+   ///
+   /// * The 'route' function will panic if a wildcard is not the last
+   ///   segment of `pattern`.
+   pub fn route<F>(&mut self, method: &str, pattern: &str, handler: F) -> &mut Router
+      where
+         F: Fn(&Request, &Params) -> Response + Send + Sync + 'static
+   {
+      let parts: Vec<&str> = pattern.trim_start_matches('/').split('/').collect();
+
+      let segments = parts.iter().enumerate().map(|(i, part)| {
+         if let Some(name) = part.strip_prefix(':') {
+            Segment::Param(name.to_string())
+         } else if let Some(name) = part.strip_prefix('*') {
+            assert!(i == parts.len() - 1, "wildcard must be the last segment of {:?}", pattern);
+
+            Segment::Wildcard(name.to_string())
+         } else {
+            Segment::Literal(part.to_string())
+         }
+      }).collect();
+
+      self.routes.push(Route {
+         method: method.to_string(),
+         segments,
+         handler: Box::new(handler),
+      });
+
+      self
+   }
+
+   pub fn get<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+      where
+         F: Fn(&Request, &Params) -> Response + Send + Sync + 'static
+   {
+      self.route("GET", pattern, handler)
+   }
+
+   pub fn post<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+      where
+         F: Fn(&Request, &Params) -> Response + Send + Sync + 'static
+   {
+      self.route("POST", pattern, handler)
+   }
+
+   /// Sets the handler for paths no route matches.
+   pub fn not_found<F>(&mut self, handler: F) -> &mut Router
+      where
+         F: Fn(&Request, &Params) -> Response + Send + Sync + 'static
+   {
+      self.not_found = Box::new(handler);
+
+      self
+   }
+
+   /// Answers a request with the first route matching its method and path.
+   /// If routes match the path but none the method, answers 405 listing the
+   /// methods that would have matched in `Allow`.
+   pub fn handle(&self, request: &Request) -> Response {
+      let mut allowed: Vec<&str> = Vec::new();
+
+      for route in &self.routes {
+         let params = match match_path(&route.segments, request.path()) {
+            Some(params) => params,
+            None => continue,
+         };
+
+         if route.method == request.method {
+            return (route.handler)(request, &params);
+         }
+
+         if !allowed.contains(&route.method.as_str()) {
+            allowed.push(&route.method);
+         }
+      }
+
+      if allowed.is_empty() {
+         return (self.not_found)(request, &Params::default());
+      }
+
+      allowed.sort();
+
+      Response::new(405)
+         .header("Allow", &allowed.join(", "))
+         .header("Content-Type", "text/plain; charset=utf-8")
+         .body("Method not allowed\n")
+   }
+}
+
+impl Default for Router {
+   fn default() -> Router {
+      Router::new()
+   }
+}
+
+fn match_path(segments: &[Segment], path: &str) -> Option<Params> {
+   let mut parts = path.strip_prefix('/').unwrap_or(path).split('/');
+   let mut params = Params::default();
+
+   for segment in segments {
+      match *segment {
+         Segment::Literal(ref literal) => {
+            if parts.next()? != literal {
+               return None;
+            }
+         },
+         Segment::Param(ref name) => {
+            let part = parts.next().filter(|part| !part.is_empty())?;
+
+            params.params.push((name.clone(), percent_decode(part)?));
+         },
+         Segment::Wildcard(ref name) => {
+            let rest: Vec<&str> = parts.by_ref().collect();
+
+            params.params.push((name.clone(), percent_decode(&rest.join("/"))?));
+         },
+      }
+   }
+
+   match parts.next() {
+      Some(_) => None,
+      None => Some(params),
+   }
+}
+
+/// Decodes `%XX` escapes, failing on bad escapes or if the result is not
+/// UTF-8.
+pub fn percent_decode(s: &str) -> Option<String> {
+   let bytes = s.as_bytes();
+   let mut decoded = Vec::with_capacity(bytes.len());
+   let mut i = 0;
+
+   while i < bytes.len() {
+      if bytes[i] == b'%' {
+         let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+
+         decoded.push(u8::from_str_radix(hex, 16).ok()?);
+         i += 3;
+      } else {
+         decoded.push(bytes[i]);
+         i += 1;
+      }
+   }
+
+   String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use http::Version;
+
+   fn request(method: &str, target: &str) -> Request {
+      Request {
+         method: method.to_string(),
+         target: target.to_string(),
+         version: Version::Http11,
+         headers: Vec::new(),
+         body: Vec::new(),
+         trailers: Vec::new(),
+      }
+   }
+
+   fn body(response: &Response) -> String {
+      String::from_utf8(response.body.clone()).unwrap()
+   }
+
+   fn router() -> Router {
+      let mut router = Router::new();
+
+      router
+         .get("/", |_, _| Response::new(200).body("home"))
+         .get("/users/:id", |_, params| {
+            Response::new(200).body(format!("user {}", params.get("id").unwrap()))
+         })
+         .post("/users/:id", |_, _| Response::new(204))
+         .route("DELETE", "/users/:id", |_, _| Response::new(204))
+         .get("/users/:id/posts/:post", |_, params| {
+            Response::new(200).body(format!("{}/{}", params.get("id").unwrap(), params.get("post").unwrap()))
+         })
+         .get("/static/*path", |_, params| {
+            Response::new(200).body(format!("file {}", params.get("path").unwrap()))
+         });
+
+      router
+   }
+
+   #[test]
+   fn dispatches_by_path() {
+      let router = router();
+
+      assert_eq!("home", body(&router.handle(&request("GET", "/"))));
+      assert_eq!("user 42", body(&router.handle(&request("GET", "/users/42?full=1"))));
+      assert_eq!("7/9", body(&router.handle(&request("GET", "/users/7/posts/9"))));
+      assert_eq!(204, router.handle(&request("POST", "/users/42")).status);
+   }
+
+   #[test]
+   fn parameters_are_decoded() {
+      let router = router();
+
+      assert_eq!("user a b", body(&router.handle(&request("GET", "/users/a%20b"))));
+      assert_eq!(404, router.handle(&request("GET", "/users/%zz")).status);
+   }
+
+   #[test]
+   fn wildcard_takes_the_rest() {
+      let router = router();
+
+      assert_eq!("file css/site.css", body(&router.handle(&request("GET", "/static/css/site.css"))));
+      assert_eq!("file ", body(&router.handle(&request("GET", "/static/"))));
+      assert_eq!("file ", body(&router.handle(&request("GET", "/static"))));
+   }
+
+   #[test]
+   fn unmatched_paths_are_not_found() {
+      let router = router();
+
+      for target in &["/users", "/users/", "/users/1/extra", "/nope", "//"] {
+         assert_eq!(404, router.handle(&request("GET", target)).status, "{}", target);
+      }
+   }
+
+   #[test]
+   fn method_mismatch_lists_allowed_methods() {
+      let response = router().handle(&request("PUT", "/users/1"));
+
+      assert_eq!(405, response.status);
+      assert!(response.headers.contains(&("Allow".to_string(), "DELETE, GET, POST".to_string())));
+   }
+
+   #[test]
+   fn custom_not_found() {
+      let mut router = Router::new();
+
+      router.not_found(|request, _| Response::new(404).body(request.path()));
+
+      assert_eq!("/missing", body(&router.handle(&request("GET", "/missing?q"))));
+   }
+}
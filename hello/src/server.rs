@@ -0,0 +1,901 @@
+/* extern create usings */
+use http::{ParseError, Request, RequestReader, Response, Version};
+use router::Router;
+use tls;
+use {RejectionPolicy, ThreadPool};
+
+use rustls::ServerConfig;
+
+/* System includes */
+use std::fmt;
+use std::io;
+use std::thread;
+
+use std::io::prelude::*;
+
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown as Close, SocketAddr, TcpListener, TcpStream};
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+use std::time::{Duration, Instant};
+
+/** How long connections are kept open between requests. */
+#[derive(Debug, Clone)]
+pub struct ConnectionOptions {
+   /// How long to wait for the next request before closing.
+   pub idle_timeout: Duration,
+   /// How long a request may take to arrive, from its first byte to the end
+   /// of its body. Requests still incomplete then are answered with 408.
+   pub request_timeout: Duration,
+   /// How many requests to answer before closing.
+   pub max_requests: usize,
+}
+
+impl Default for ConnectionOptions {
+   fn default() -> ConnectionOptions {
+      ConnectionOptions {
+         idle_timeout: Duration::from_secs(5),
+         request_timeout: Duration::from_secs(30),
+         max_requests: 100,
+      }
+   }
+}
+
+/// How often blocked reads wake up to check for shutdown.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Answers requests on a connection until the client or the options say to
+/// close it.
+///
+/// ## Input:
+///
+/// * `stream` - The connection.
+/// * `router` - Answers each request.
+/// * `options` - When to close the connection.
+/// * `stopping` - Once set, the connection closes after the request in
+///   progress, or straight away if it is idle.
+///
+/// ## Returns
+///
+/// * The number of requests answered.
+pub fn handle_connection(stream: TcpStream, router: &Router, options: &ConnectionOptions, stopping: &AtomicBool) -> usize {
+   if let Err(e) = set_poll_timeout(&stream, options) {
+      warn!("Failed to set idle timeout: {}", e);
+
+      return 0;
+   }
+
+   serve(stream, router, options, stopping)
+}
+
+/// Makes reads on `stream` time out often enough for `serve` to notice the
+/// idle timeout and shutdown.
+pub(crate) fn set_poll_timeout(stream: &TcpStream, options: &ConnectionOptions) -> io::Result<()> {
+   stream.set_read_timeout(Some(options.idle_timeout.min(POLL_INTERVAL)))
+}
+
+/// Answers requests on any stream, as `handle_connection` does. Requests
+/// are answered in order, so pipelined requests get their responses in the
+/// order they were sent.
+pub fn serve<S: Read + Write>(stream: S, router: &Router, options: &ConnectionOptions, stopping: &AtomicBool) -> usize {
+   let mut reader = RequestReader::new(stream).request_timeout(options.request_timeout);
+   let mut served = 0;
+   let mut idle_since = Instant::now();
+
+   loop {
+      let request = match reader.read_request() {
+         Ok(Some(request)) => request,
+         Ok(None) => break,
+         Err(ref e) if is_timeout(e) => {
+            // Once a request has started, the reader's request timeout
+            // applies instead.
+            let idle = reader.buffered() == 0;
+
+            if idle && (stopping.load(Ordering::SeqCst) || idle_since.elapsed() >= options.idle_timeout) {
+               break;
+            }
+
+            continue;
+         },
+         Err(e) => {
+            match e.response() {
+               Some(response) => { let _ = response.write_to(reader.get_mut()); },
+               None => debug!("Dropping connection: {}", e),
+            }
+
+            break;
+         },
+      };
+
+      served += 1;
+
+      let mut response = router.handle(&request);
+
+      // The thread is the connection's anyway, so it waits out the delay.
+      if let Some(delay) = response.delay.take() {
+         thread::sleep(delay);
+      }
+
+      let (response, keep_alive) = keep_alive(&request, response, served, options, stopping);
+
+      if let Err(e) = response.write_to(reader.get_mut()) {
+         debug!("Failed to write response: {}", e);
+
+         break;
+      }
+
+      if !keep_alive {
+         break;
+      }
+
+      idle_since = Instant::now();
+   }
+
+   served
+}
+
+/// Decides whether the connection stays open after `response`, the answer
+/// to the `served`th request on it, and says so in the `Connection` header.
+pub(crate) fn keep_alive(
+   request: &Request,
+   mut response: Response,
+   served: usize,
+   options: &ConnectionOptions,
+   stopping: &AtomicBool
+) -> (Response, bool) {
+   let keep_alive = wants_keep_alive(request)
+      && !has_token(&response.headers, "Connection", "close")
+      && served < options.max_requests
+      && !stopping.load(Ordering::SeqCst);
+
+   if !keep_alive {
+      response = response.header("Connection", "close");
+   } else if request.version == Version::Http10 {
+      response = response.header("Connection", "keep-alive");
+   }
+
+   (response, keep_alive)
+}
+
+/// HTTP/1.1 connections persist unless the client asks to close them, and
+/// HTTP/1.0 connections close unless the client asks to keep them.
+fn wants_keep_alive(request: &Request) -> bool {
+   match request.version {
+      Version::Http11 => !has_token(&request.headers, "Connection", "close"),
+      Version::Http10 => has_token(&request.headers, "Connection", "keep-alive"),
+   }
+}
+
+/// Whether a comma-separated header such as `Connection` lists `token`.
+fn has_token(headers: &[(String, String)], name: &str, token: &str) -> bool {
+   headers.iter()
+      .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+      .flat_map(|(_, value)| value.split(','))
+      .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+fn is_timeout(e: &ParseError) -> bool {
+   match *e {
+      ParseError::Io(ref e) => e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut,
+      _ => false,
+   }
+}
+
+/** Options for a whole server. */
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+   /// The number of threads answering connections.
+   pub threads: usize,
+   /// How many accepted connections may wait for a thread. Connections
+   /// beyond that are answered with 503 Service Unavailable, or over TLS
+   /// simply closed.
+   pub queue_capacity: usize,
+   pub connection: ConnectionOptions,
+   /// How long requests in progress at shutdown get to finish.
+   pub drain_timeout: Duration,
+   /// How often to log the thread pool's metrics, if at all.
+   pub stats_interval: Option<Duration>,
+   /// Serve HTTPS with this configuration, from `tls::load_config`,
+   /// rather than plain HTTP.
+   pub tls: Option<Arc<ServerConfig>>,
+}
+
+impl Default for ServerOptions {
+   fn default() -> ServerOptions {
+      ServerOptions {
+         threads: 4,
+         queue_capacity: 128,
+         connection: ConnectionOptions::default(),
+         drain_timeout: Duration::from_secs(10),
+         stats_interval: None,
+         tls: None,
+      }
+   }
+}
+
+/** Accepts connections and answers them on a ThreadPool until shut down. */
+pub struct Server {
+   listener: TcpListener,
+   router: Arc<Router>,
+   options: ServerOptions,
+   stopping: Arc<AtomicBool>,
+}
+
+/** Shuts a running Server down from another thread. */
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+   stopping: Arc<AtomicBool>,
+   address: SocketAddr,
+}
+
+/** What a Server did before it shut down. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+   /// Connections accepted.
+   pub connections: usize,
+   /// Requests answered.
+   pub requests: usize,
+   /// Connections answered with 503 because every thread was busy and
+   /// the queue was full.
+   pub rejected: usize,
+   /// Connections still open when the drain timeout ran out.
+   pub abandoned: usize,
+   pub uptime: Duration,
+}
+
+impl fmt::Display for Summary {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(
+         f,
+         "Served {} requests on {} connections in {:.1}s",
+         self.requests,
+         self.connections,
+         self.uptime.as_secs_f64()
+      )?;
+
+      if self.rejected > 0 {
+         write!(f, "; turned away {} connections", self.rejected)?;
+      }
+
+      if self.abandoned > 0 {
+         write!(f, "; abandoned {} connections still in progress", self.abandoned)?;
+      }
+
+      Ok(())
+   }
+}
+
+impl Server {
+   /// Create a new Server.
+   ///
+   /// ## Input:
+   ///
+   /// * `listener` - Where to accept connections.
+   /// * `router` - Answers each request.
+   /// * `options` - How to run the server.
+   pub fn new(listener: TcpListener, router: Router, options: ServerOptions) -> Server {
+      Server {
+         listener,
+         router: Arc::new(router),
+         options,
+         stopping: Arc::new(AtomicBool::new(false)),
+      }
+   }
+
+   pub fn local_addr(&self) -> io::Result<SocketAddr> {
+      self.listener.local_addr()
+   }
+
+   /// Returns a handle to shut the server down with.
+   pub fn shutdown_handle(&self) -> io::Result<Shutdown> {
+      Ok(Shutdown::new(Arc::clone(&self.stopping), self.local_addr()?))
+   }
+
+   /// Answers connections until shut down, then gives the connections in
+   /// progress up to `drain_timeout` to finish. If they do, the pool's
+   /// threads are joined; if not, the pool is detached, leaving its workers
+   /// to stop once their connections are done, and the summary counts the
+   /// connections as abandoned.
+   pub fn run(self) -> Summary {
+      let started = Instant::now();
+      let pool = ThreadPool::builder()
+         .min_threads(self.options.threads)
+         .max_threads(self.options.threads)
+         .queue_capacity(self.options.queue_capacity)
+         .rejection_policy(RejectionPolicy::Reject)
+         .build();
+
+      let stats = self.options.stats_interval.map(|interval| {
+         let spawner = pool.spawner();
+
+         pool.execute_every(interval, move || info!("{}", spawner.metrics()))
+      });
+
+      let active = Arc::new((Mutex::new(0), Condvar::new()));
+      let requests = Arc::new(AtomicUsize::new(0));
+      let mut connections = 0;
+      let mut rejected = 0;
+
+      for stream in self.listener.incoming() {
+         if self.stopping.load(Ordering::SeqCst) {
+            break;
+         }
+
+         let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+               error!("Failed to accept a connection: {}", e);
+
+               continue;
+            },
+         };
+
+         // Kept to answer on if the pool turns the connection away.
+         let overflow = match stream.try_clone() {
+            Ok(overflow) => overflow,
+            Err(e) => {
+               error!("Failed to clone a connection: {}", e);
+
+               continue;
+            },
+         };
+
+         *active.0.lock().unwrap() += 1;
+
+         let guard = Active(Arc::clone(&active));
+         let router = Arc::clone(&self.router);
+         let options = self.options.connection.clone();
+         let stopping = Arc::clone(&self.stopping);
+         let requests = Arc::clone(&requests);
+         let tls = self.options.tls.clone();
+
+         let job = pool.try_execute(move || {
+            let served = match tls {
+               Some(config) => tls::handle_connection(stream, config, &router, &options, &stopping),
+               None => handle_connection(stream, &router, &options, &stopping),
+            };
+
+            requests.fetch_add(served, Ordering::SeqCst);
+
+            drop(guard);
+         });
+
+         match job {
+            Ok(()) => connections += 1,
+            Err(_) => {
+               rejected += 1;
+
+               // A 503 in the clear would mean nothing to a TLS client.
+               if self.options.tls.is_none() {
+                  service_unavailable(overflow);
+               }
+            },
+         }
+      }
+
+      info!("Stopped accepting connections; draining.");
+
+      if let Some(stats) = stats {
+         stats.cancel();
+      }
+
+      let deadline = Instant::now() + self.options.drain_timeout;
+      let mut remaining = active.0.lock().unwrap();
+
+      while *remaining > 0 {
+         let now = Instant::now();
+
+         if now >= deadline {
+            break;
+         }
+
+         remaining = active.1.wait_timeout(remaining, deadline - now).unwrap().0;
+      }
+
+      let abandoned = *remaining;
+
+      drop(remaining);
+
+      if abandoned == 0 {
+         drop(pool);
+      } else {
+         pool.detach();
+      }
+
+      Summary {
+         connections,
+         requests: requests.load(Ordering::SeqCst),
+         rejected,
+         abandoned,
+         uptime: started.elapsed(),
+      }
+   }
+}
+
+impl Shutdown {
+   /// A handle for a server that stops once `stopping` is set, and wakes
+   /// when `address` is connected to.
+   pub(crate) fn new(stopping: Arc<AtomicBool>, address: SocketAddr) -> Shutdown {
+      Shutdown {
+         stopping,
+         address,
+      }
+   }
+
+   /// Stops the server accepting connections and starts the drain. Safe to
+   /// call more than once.
+   pub fn trigger(&self) {
+      self.stopping.store(true, Ordering::SeqCst);
+
+      // Wake the accept loop with a connection of our own.
+      let mut address = self.address;
+
+      if address.ip().is_unspecified() {
+         address.set_ip(match address {
+            SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+         });
+      }
+
+      let _ = TcpStream::connect_timeout(&address, Duration::from_secs(1));
+   }
+}
+
+/// The answer for a connection the pool has no room for.
+pub(crate) fn busy() -> Response {
+   Response::new(503)
+      .header("Content-Type", "text/plain; charset=utf-8")
+      .header("Retry-After", "1")
+      .header("Connection", "close")
+      .body("Server busy; try again shortly.\n")
+}
+
+/// Answers a connection the pool has no room for with 503.
+fn service_unavailable(mut stream: TcpStream) {
+   if busy().write_to(&mut stream).is_err() {
+      return;
+   }
+
+   let _ = stream.shutdown(Close::Write);
+   let _ = stream.set_read_timeout(Some(Duration::from_millis(10)));
+
+   discard_input(&mut stream);
+}
+
+/// Reads what the client has sent, before closing a connection whose
+/// write side is already shut down. Closing with input unread makes the
+/// kernel reset the connection, which can discard the last response before
+/// the client reads it.
+pub(crate) fn discard_input<R: Read>(stream: &mut R) {
+   let _ = stream.read(&mut [0; 4096]);
+}
+
+/** Counts a connection as active until dropped, even if its job panics. */
+struct Active(Arc<(Mutex<usize>, Condvar)>);
+
+impl Drop for Active {
+   fn drop(&mut self) {
+      let mut active = match (self.0).0.lock() {
+         Ok(active) => active,
+         Err(poisoned) => poisoned.into_inner(),
+      };
+
+      *active -= 1;
+      (self.0).1.notify_all();
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use test_support::{self, Started};
+
+   use std::io::Cursor;
+   use std::sync::mpsc;
+
+   /** A connection whose input is fixed up front. */
+   struct Duplex {
+      input: Cursor<Vec<u8>>,
+      output: Vec<u8>,
+   }
+
+   impl Read for Duplex {
+      fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+         self.input.read(buf)
+      }
+   }
+
+   impl Write for Duplex {
+      fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+         self.output.write(buf)
+      }
+
+      fn flush(&mut self) -> io::Result<()> {
+         Ok(())
+      }
+   }
+
+   /** A connection whose input arrives `step` bytes at a time, 10ms apart.
+    * With `timeouts`, every other wait ends in a read timeout instead.
+    */
+   struct Slow {
+      input: Cursor<Vec<u8>>,
+      output: Vec<u8>,
+      step: usize,
+      timeouts: bool,
+      timed_out: bool,
+   }
+
+   impl Read for Slow {
+      fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+         thread::sleep(Duration::from_millis(10));
+
+         self.timed_out = self.timeouts && !self.timed_out;
+
+         if self.timed_out {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "timed out"));
+         }
+
+         let step = self.step.min(buf.len());
+
+         self.input.read(&mut buf[..step])
+      }
+   }
+
+   impl Write for Slow {
+      fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+         self.output.write(buf)
+      }
+
+      fn flush(&mut self) -> io::Result<()> {
+         Ok(())
+      }
+   }
+
+   fn router() -> Router {
+      let mut router = Router::new();
+
+      router
+         .get("/:name", |_, params| Response::new(200).body(params.get("name").unwrap().to_string()))
+         .post("/echo", |request, _| Response::new(200).body(request.body.clone()));
+
+      router
+   }
+
+   /// Serves `input` and returns how many requests were answered and the
+   /// responses written.
+   fn exchange(input: &str, options: &ConnectionOptions) -> (usize, String) {
+      let mut duplex = Duplex {
+         input: Cursor::new(input.as_bytes().to_vec()),
+         output: Vec::new(),
+      };
+
+      let served = serve(&mut duplex, &router(), options, &AtomicBool::new(false));
+
+      (served, String::from_utf8(duplex.output).unwrap())
+   }
+
+   #[test]
+   fn pipelined_requests_are_answered_in_order() {
+      let (served, output) = exchange(
+         "GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\nGET /c HTTP/1.1\r\n\r\n",
+         &ConnectionOptions::default()
+      );
+
+      assert_eq!(3, served);
+
+      let bodies: Vec<&str> = output.split("HTTP/1.1 200 OK").skip(1).map(|response| {
+         &response[response.find("\r\n\r\n").unwrap() + 4..]
+      }).collect();
+
+      assert_eq!(vec!["a", "b", "c"], bodies);
+   }
+
+   #[test]
+   fn connection_close_stops_the_connection() {
+      let (served, output) = exchange(
+         "GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\nConnection: close\r\n\r\nGET /c HTTP/1.1\r\n\r\n",
+         &ConnectionOptions::default()
+      );
+
+      assert_eq!(2, served);
+      assert!(output.ends_with("Connection: close\r\nContent-Length: 1\r\n\r\nb"));
+   }
+
+   #[test]
+   fn trailers_do_not_close_the_connection() {
+      let (served, output) = exchange(
+         "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nConnection: close\r\n\r\nGET /b HTTP/1.1\r\n\r\n",
+         &ConnectionOptions::default()
+      );
+
+      assert_eq!(2, served);
+      assert!(!output.contains("Connection: close"));
+      assert!(output.ends_with("\r\n\r\nb"));
+   }
+
+   #[test]
+   fn http10_needs_keep_alive() {
+      let (served, output) = exchange("GET /a HTTP/1.0\r\n\r\nGET /b HTTP/1.0\r\n\r\n", &ConnectionOptions::default());
+
+      assert_eq!(1, served);
+      assert!(output.contains("Connection: close"));
+
+      let (served, output) = exchange(
+         "GET /a HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\nGET /b HTTP/1.0\r\n\r\n",
+         &ConnectionOptions::default()
+      );
+
+      assert_eq!(2, served);
+      assert!(output.contains("Connection: keep-alive"));
+   }
+
+   #[test]
+   fn max_requests_per_connection() {
+      let options = ConnectionOptions {
+         max_requests: 2,
+         ..ConnectionOptions::default()
+      };
+
+      let (served, output) = exchange("GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\nGET /c HTTP/1.1\r\n\r\n", &options);
+
+      assert_eq!(2, served);
+      assert_eq!(1, output.matches("Connection: close").count());
+   }
+
+   #[test]
+   fn malformed_request_after_good_one() {
+      let (served, output) = exchange("GET /a HTTP/1.1\r\n\r\nBAD\r\n\r\n", &ConnectionOptions::default());
+
+      assert_eq!(1, served);
+      assert!(output.contains("HTTP/1.1 400 Bad Request"));
+   }
+
+   #[test]
+   fn slow_uploads_outlast_the_idle_timeout() {
+      let mut slow = Slow {
+         input: Cursor::new(b"POST /echo HTTP/1.1\r\nContent-Length: 10\r\n\r\n0123456789".to_vec()),
+         output: Vec::new(),
+         step: 4,
+         timeouts: true,
+         timed_out: false,
+      };
+
+      let options = ConnectionOptions {
+         idle_timeout: Duration::from_millis(50),
+         ..ConnectionOptions::default()
+      };
+
+      assert_eq!(1, serve(&mut slow, &router(), &options, &AtomicBool::new(false)));
+      assert!(String::from_utf8(slow.output).unwrap().ends_with("\r\n\r\n0123456789"));
+   }
+
+   #[test]
+   fn trickling_requests_time_out() {
+      let mut input = b"GET /a HTTP/1.1\r\nX-Slow: ".to_vec();
+
+      input.resize(4096, b'x');
+
+      let mut slow = Slow {
+         input: Cursor::new(input),
+         output: Vec::new(),
+         step: 1,
+         timeouts: false,
+         timed_out: false,
+      };
+
+      let options = ConnectionOptions {
+         request_timeout: Duration::from_millis(200),
+         ..ConnectionOptions::default()
+      };
+
+      let start = Instant::now();
+
+      assert_eq!(0, serve(&mut slow, &router(), &options, &AtomicBool::new(false)));
+      assert!(String::from_utf8(slow.output).unwrap().starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+      assert!(start.elapsed() < Duration::from_secs(2));
+   }
+
+   #[test]
+   fn idle_connections_time_out() {
+      let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+      let address = listener.local_addr().unwrap();
+
+      let server = thread::spawn(move || {
+         let (stream, _) = listener.accept().unwrap();
+         let options = ConnectionOptions {
+            idle_timeout: Duration::from_millis(100),
+            ..ConnectionOptions::default()
+         };
+
+         handle_connection(stream, &router(), &options, &AtomicBool::new(false))
+      });
+
+      let mut client = TcpStream::connect(address).unwrap();
+      let start = Instant::now();
+
+      client.write_all(b"GET /a HTTP/1.1\r\n\r\n").unwrap();
+
+      let mut output = String::new();
+
+      client.read_to_string(&mut output).unwrap();
+
+      assert!(output.ends_with("\r\n\r\na"));
+      assert!(start.elapsed() < Duration::from_secs(5));
+      assert_eq!(1, server.join().unwrap());
+   }
+
+   fn start(slow: Duration, drain_timeout: Duration) -> Started {
+      let options = ServerOptions {
+         threads: 2,
+         drain_timeout,
+         ..ServerOptions::default()
+      };
+
+      test_support::start::<Server>(slow, options)
+   }
+
+   #[test]
+   fn shutdown_summarises() {
+      let (address, shutdown, _, server) = start(Duration::from_secs(0), Duration::from_secs(5));
+
+      let mut client = TcpStream::connect(address).unwrap();
+      let mut output = String::new();
+
+      client.write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+      client.read_to_string(&mut output).unwrap();
+
+      let mut client = TcpStream::connect(address).unwrap();
+
+      client.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+      client.read_to_string(&mut output).unwrap();
+
+      assert_eq!(3, output.matches("hello").count());
+
+      shutdown.trigger();
+
+      let summary = server.join().unwrap();
+
+      assert_eq!(2, summary.connections);
+      assert_eq!(3, summary.requests);
+      assert_eq!(0, summary.abandoned);
+   }
+
+   #[test]
+   fn delayed_responses_are_sent_late() {
+      let (address, shutdown, _, server) = start(Duration::from_millis(200), Duration::from_secs(5));
+      let start = Instant::now();
+
+      assert!(test_support::get(address, "/delayed").ends_with("delayed"));
+      assert!(start.elapsed() >= Duration::from_millis(200));
+
+      shutdown.trigger();
+      server.join().unwrap();
+   }
+
+   #[test]
+   fn requests_in_progress_finish() {
+      let (address, shutdown, started, server) = start(Duration::from_millis(300), Duration::from_secs(5));
+
+      let mut client = TcpStream::connect(address).unwrap();
+
+      client.write_all(b"GET /slow HTTP/1.1\r\n\r\n").unwrap();
+      started.recv().unwrap();
+      shutdown.trigger();
+
+      let mut output = String::new();
+
+      client.read_to_string(&mut output).unwrap();
+
+      assert!(output.contains("Connection: close"));
+      assert!(output.ends_with("slow"));
+      assert_eq!(0, server.join().unwrap().abandoned);
+      assert!(TcpStream::connect_timeout(&address, Duration::from_secs(1)).is_err());
+   }
+
+   #[test]
+   fn idle_connections_close_on_shutdown() {
+      let (address, shutdown, _, server) = start(Duration::from_secs(0), Duration::from_secs(5));
+
+      let mut client = TcpStream::connect(address).unwrap();
+      let mut response = [0; 256];
+
+      client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+      let read = client.read(&mut response).unwrap();
+
+      assert!(String::from_utf8_lossy(&response[..read]).ends_with("hello"));
+
+      let start = Instant::now();
+
+      shutdown.trigger();
+
+      assert_eq!(0, server.join().unwrap().abandoned);
+      assert_eq!(0, client.read(&mut response).unwrap());
+      assert!(start.elapsed() < Duration::from_secs(2));
+   }
+
+   #[test]
+   fn drain_timeout_abandons_slow_requests() {
+      let (address, shutdown, started, server) = start(Duration::from_secs(3), Duration::from_millis(100));
+
+      let mut client = TcpStream::connect(address).unwrap();
+
+      client.write_all(b"GET /slow HTTP/1.1\r\n\r\n").unwrap();
+      started.recv().unwrap();
+
+      let start = Instant::now();
+
+      shutdown.trigger();
+
+      let summary = server.join().unwrap();
+
+      assert_eq!(1, summary.abandoned);
+      assert!(start.elapsed() < Duration::from_secs(2));
+   }
+
+   #[test]
+   fn full_queue_answers_503() {
+      let (release, released) = mpsc::channel::<()>();
+      let (started, start) = mpsc::channel();
+      let released = Mutex::new(released);
+      let started = Mutex::new(started);
+      let mut router = Router::new();
+
+      router.get("/block", move |_, _| {
+         started.lock().unwrap().send(()).unwrap();
+         released.lock().unwrap().recv().unwrap();
+
+         Response::new(200).body("done")
+      });
+
+      let options = ServerOptions {
+         threads: 1,
+         queue_capacity: 1,
+         ..ServerOptions::default()
+      };
+
+      let server = Server::new(TcpListener::bind("127.0.0.1:0").unwrap(), router, options);
+      let address = server.local_addr().unwrap();
+      let shutdown = server.shutdown_handle().unwrap();
+      let server = thread::spawn(move || server.run());
+
+      // One connection busy on the only thread, one waiting in the queue.
+      let mut busy = TcpStream::connect(address).unwrap();
+
+      busy.write_all(b"GET /block HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+      start.recv().unwrap();
+
+      let mut queued = TcpStream::connect(address).unwrap();
+
+      queued.write_all(b"GET /block HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+
+      let mut turned_away = TcpStream::connect(address).unwrap();
+      let mut output = String::new();
+
+      turned_away.write_all(b"GET /block HTTP/1.1\r\n\r\n").unwrap();
+      turned_away.read_to_string(&mut output).unwrap();
+
+      assert!(output.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+      assert!(output.contains("Retry-After: 1"));
+
+      for stream in [&mut busy, &mut queued].iter_mut() {
+         let mut output = String::new();
+
+         release.send(()).unwrap();
+         stream.read_to_string(&mut output).unwrap();
+
+         assert!(output.ends_with("done"));
+      }
+
+      shutdown.trigger();
+
+      let summary = server.join().unwrap();
+
+      assert_eq!(2, summary.connections);
+      assert_eq!(1, summary.rejected);
+      assert!(summary.to_string().contains("turned away 1 connections"));
+   }
+}
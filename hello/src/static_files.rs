@@ -0,0 +1,373 @@
+/* extern create usings */
+use http::{self, Request, Response};
+
+/* System includes */
+use std::fs;
+use std::io;
+
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Content types by file extension.
+const MIME_TYPES: &[(&str, &str)] = &[
+   ("css", "text/css; charset=utf-8"),
+   ("gif", "image/gif"),
+   ("gz", "application/gzip"),
+   ("htm", "text/html; charset=utf-8"),
+   ("html", "text/html; charset=utf-8"),
+   ("ico", "image/x-icon"),
+   ("jpeg", "image/jpeg"),
+   ("jpg", "image/jpeg"),
+   ("js", "text/javascript; charset=utf-8"),
+   ("json", "application/json"),
+   ("md", "text/markdown; charset=utf-8"),
+   ("mjs", "text/javascript; charset=utf-8"),
+   ("mp3", "audio/mpeg"),
+   ("mp4", "video/mp4"),
+   ("pdf", "application/pdf"),
+   ("png", "image/png"),
+   ("svg", "image/svg+xml"),
+   ("ttf", "font/ttf"),
+   ("txt", "text/plain; charset=utf-8"),
+   ("wasm", "application/wasm"),
+   ("webm", "video/webm"),
+   ("webp", "image/webp"),
+   ("woff", "font/woff"),
+   ("woff2", "font/woff2"),
+   ("xml", "application/xml"),
+   ("zip", "application/zip"),
+];
+
+/// Returns the content type for a file name, by its extension.
+pub fn mime_type(path: &Path) -> &'static str {
+   let extension = path.extension()
+      .and_then(|extension| extension.to_str())
+      .map(|extension| extension.to_ascii_lowercase());
+
+   extension
+      .and_then(|extension| MIME_TYPES.iter().find(|(known, _)| *known == extension))
+      .map(|(_, mime)| *mime)
+      .unwrap_or("application/octet-stream")
+}
+
+/** Serves the files below a root directory. */
+pub struct StaticFiles {
+   root: PathBuf,
+}
+
+impl StaticFiles {
+   /// Create a new StaticFiles.
+   ///
+   /// ## Input:
+   ///
+   /// * `root` - The directory to serve. Nothing outside it is served, even
+   ///   through symlinks.
+   pub fn new<P: AsRef<Path>>(root: P) -> io::Result<StaticFiles> {
+      Ok(StaticFiles {
+         root: fs::canonicalize(root)?,
+      })
+   }
+
+   /// Answers a request for a file.
+   ///
+   /// ## Input:
+   ///
+   /// * `request` - The request, for its conditional headers and, when
+   ///   `path` is a directory, its URL.
+   /// * `path` - The decoded path of the file relative to the root.
+   pub fn serve(&self, request: &Request, path: &str) -> Response {
+      let file = match self.resolve(path) {
+         Ok(file) => file,
+         Err(status) => return error(status),
+      };
+
+      let metadata = match fs::metadata(&file) {
+         Ok(metadata) => metadata,
+         Err(_) => return error(404),
+      };
+
+      if !metadata.is_dir() {
+         return serve_file(request, &file, &metadata);
+      }
+
+      // Relative links on the index page need the URL to end in a slash.
+      if !request.path().ends_with('/') {
+         let mut location = format!("{}/", request.path());
+
+         if let Some(query) = request.target.find('?') {
+            location.push_str(&request.target[query..]);
+         }
+
+         return Response::new(301).header("Location", &location);
+      }
+
+      let index = file.join("index.html");
+
+      match fs::metadata(&index) {
+         Ok(ref metadata) if metadata.is_file() => serve_file(request, &index, metadata),
+         _ => listing(request.path(), &file),
+      }
+   }
+
+   /// Maps `path` to a file below the root, or to the status to answer with.
+   fn resolve(&self, path: &str) -> Result<PathBuf, u16> {
+      let mut file = self.root.clone();
+
+      for part in path.split('/') {
+         if part.contains('\\') || part.contains('\0') {
+            return Err(404);
+         }
+
+         let mut components = Path::new(part).components();
+
+         match (components.next(), components.next()) {
+            (None, _) | (Some(Component::CurDir), None) => {},
+            (Some(Component::Normal(name)), None) => file.push(name),
+            _ => return Err(403),
+         }
+      }
+
+      // Symlinks may point anywhere; only follow them if they stay inside.
+      match fs::canonicalize(&file) {
+         Ok(ref resolved) if resolved.starts_with(&self.root) => Ok(file),
+         Ok(_) => Err(403),
+         Err(_) => Err(404),
+      }
+   }
+}
+
+fn serve_file(request: &Request, file: &Path, metadata: &fs::Metadata) -> Response {
+   let modified = metadata.modified().ok();
+   let mtime = modified
+      .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+      .map(|since| since.as_secs())
+      .unwrap_or(0);
+
+   let etag = format!("\"{:x}-{:x}\"", metadata.len(), mtime);
+   let last_modified = modified.map(http::http_date);
+
+   let not_modified = match request.header("If-None-Match") {
+      Some(tags) => tags.split(',').any(|tag| {
+         let tag = tag.trim();
+
+         tag == "*" || tag.trim_start_matches("W/") == etag
+      }),
+      None => match (request.header("If-Modified-Since").and_then(http::parse_http_date), modified) {
+         (Some(since), Some(_)) => UNIX_EPOCH + std::time::Duration::from_secs(mtime) <= since,
+         _ => false,
+      },
+   };
+
+   let mut response = Response::new(200).header("ETag", &etag);
+
+   if let Some(ref last_modified) = last_modified {
+      response = response.header("Last-Modified", last_modified);
+   }
+
+   if not_modified {
+      response.status = 304;
+
+      return response;
+   }
+
+   match fs::read(file) {
+      Ok(contents) => response.header("Content-Type", mime_type(file)).body(contents),
+      Err(_) => error(403),
+   }
+}
+
+fn listing(url: &str, dir: &Path) -> Response {
+   let mut entries: Vec<(String, bool)> = match fs::read_dir(dir) {
+      Ok(entries) => entries
+         .filter_map(|entry| entry.ok())
+         .filter_map(|entry| {
+            let is_dir = entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false);
+
+            entry.file_name().into_string().ok().map(|name| (name, is_dir))
+         })
+         .collect(),
+      Err(_) => return error(403),
+   };
+
+   entries.sort();
+
+   let title = format!("Index of {}", escape_html(url));
+   let mut html = format!("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n<ul>\n", title);
+
+   if url != "/" {
+      html.push_str("<li><a href=\"../\">../</a></li>\n");
+   }
+
+   for (name, is_dir) in entries {
+      let slash = if is_dir { "/" } else { "" };
+
+      html.push_str(&format!(
+         "<li><a href=\"{}{}\">{}{}</a></li>\n",
+         escape_html(&percent_encode(&name)), slash, escape_html(&name), slash
+      ));
+   }
+
+   html.push_str("</ul>\n</body>\n</html>\n");
+
+   Response::new(200)
+      .header("Content-Type", "text/html; charset=utf-8")
+      .body(html)
+}
+
+fn error(status: u16) -> Response {
+   Response::new(status)
+      .header("Content-Type", "text/plain; charset=utf-8")
+      .body(format!("{}\n", http::reason(status)))
+}
+
+fn escape_html(s: &str) -> String {
+   s.replace('&', "&amp;")
+      .replace('<', "&lt;")
+      .replace('>', "&gt;")
+      .replace('"', "&quot;")
+      .replace('\'', "&#39;")
+}
+
+fn percent_encode(s: &str) -> String {
+   s.bytes().map(|b| {
+      if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+         (b as char).to_string()
+      } else {
+         format!("%{:02X}", b)
+      }
+   }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use http::Version;
+   use tempfile::TempDir;
+
+   fn request(target: &str, headers: &[(&str, &str)]) -> Request {
+      Request {
+         method: "GET".to_string(),
+         target: target.to_string(),
+         version: Version::Http11,
+         headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+         body: Vec::new(),
+         trailers: Vec::new(),
+      }
+   }
+
+   fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+      response.headers.iter()
+         .find(|(header, _)| header == name)
+         .map(|(_, value)| value.as_str())
+   }
+
+   /// Creates a fresh directory tree, to serve from its `root` directory.
+   fn tree() -> TempDir {
+      let base = TempDir::new().unwrap();
+      let root = base.path().join("root");
+
+      fs::create_dir_all(root.join("docs")).unwrap();
+      fs::create_dir_all(root.join("site")).unwrap();
+      fs::write(root.join("style.css"), "body {}").unwrap();
+      fs::write(root.join("docs/a <b>.txt"), "a").unwrap();
+      fs::write(root.join("site/index.html"), "<p>site</p>").unwrap();
+      fs::write(base.path().join("secret.txt"), "secret").unwrap();
+
+      base
+   }
+
+   #[test]
+   fn serves_files_with_headers() {
+      let base = tree();
+      let files = StaticFiles::new(base.path().join("root")).unwrap();
+      let response = files.serve(&request("/static/style.css", &[]), "style.css");
+
+      assert_eq!(200, response.status);
+      assert_eq!(b"body {}".to_vec(), response.body);
+      assert_eq!(Some("text/css; charset=utf-8"), header(&response, "Content-Type"));
+      assert!(header(&response, "ETag").is_some());
+      assert!(header(&response, "Last-Modified").unwrap().ends_with(" GMT"));
+
+      assert_eq!(404, files.serve(&request("/static/missing", &[]), "missing").status);
+   }
+
+   #[test]
+   fn conditional_requests() {
+      let base = tree();
+      let files = StaticFiles::new(base.path().join("root")).unwrap();
+      let first = files.serve(&request("/style.css", &[]), "style.css");
+      let etag = header(&first, "ETag").unwrap();
+      let last_modified = header(&first, "Last-Modified").unwrap();
+
+      let by_etag = files.serve(&request("/style.css", &[("If-None-Match", &format!("\"x\", W/{}", etag))]), "style.css");
+
+      assert_eq!(304, by_etag.status);
+      assert!(by_etag.body.is_empty());
+
+      let by_date = files.serve(&request("/style.css", &[("If-Modified-Since", last_modified)]), "style.css");
+
+      assert_eq!(304, by_date.status);
+
+      let stale = files.serve(&request("/style.css", &[("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT")]), "style.css");
+
+      assert_eq!(200, stale.status);
+   }
+
+   #[test]
+   fn rejects_traversal() {
+      let base = tree();
+      let files = StaticFiles::new(base.path().join("root")).unwrap();
+
+      for path in &["../secret.txt", "docs/../../secret.txt", "docs/..", "/etc/passwd", "..\\secret.txt"] {
+         let status = files.serve(&request("/x", &[]), path).status;
+
+         assert!(status == 403 || status == 404, "{} gave {}", path, status);
+      }
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn rejects_symlink_escape() {
+      let base = tree();
+      let root = base.path().join("root");
+
+      std::os::unix::fs::symlink(root.join("../secret.txt"), root.join("escape.txt")).unwrap();
+      std::os::unix::fs::symlink(root.join("style.css"), root.join("inside.css")).unwrap();
+
+      let files = StaticFiles::new(&root).unwrap();
+
+      assert_eq!(403, files.serve(&request("/escape.txt", &[]), "escape.txt").status);
+      assert_eq!(200, files.serve(&request("/inside.css", &[]), "inside.css").status);
+   }
+
+   #[test]
+   fn directories() {
+      let base = tree();
+      let files = StaticFiles::new(base.path().join("root")).unwrap();
+
+      let redirect = files.serve(&request("/static/docs?sort=1", &[]), "docs");
+
+      assert_eq!(301, redirect.status);
+      assert_eq!(Some("/static/docs/?sort=1"), header(&redirect, "Location"));
+
+      let index = files.serve(&request("/static/site/", &[]), "site/");
+
+      assert_eq!(b"<p>site</p>".to_vec(), index.body);
+
+      let listing = files.serve(&request("/static/docs/", &[]), "docs/");
+      let html = String::from_utf8(listing.body).unwrap();
+
+      assert_eq!(200, listing.status);
+      assert!(html.contains("<title>Index of /static/docs/</title>"));
+      assert!(html.contains("<a href=\"a%20%3Cb%3E.txt\">a &lt;b&gt;.txt</a>"));
+      assert!(html.contains("href=\"../\""));
+   }
+
+   #[test]
+   fn mime_types() {
+      assert_eq!("text/html; charset=utf-8", mime_type(Path::new("a/INDEX.HTML")));
+      assert_eq!("image/png", mime_type(Path::new("logo.png")));
+      assert_eq!("application/octet-stream", mime_type(Path::new("Makefile")));
+   }
+}
@@ -0,0 +1,103 @@
+/* extern create usings */
+use http::Response;
+use reactor;
+use router::Router;
+use server::{self, ServerOptions, Shutdown, Summary};
+
+/* System includes */
+use std::thread;
+
+use std::io::prelude::*;
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+
+use std::time::Duration;
+
+/// A running server: its address, its shutdown handle, a receiver signalled
+/// whenever `/slow` is called, and the thread it runs on.
+pub type Started = (SocketAddr, Shutdown, Receiver<()>, thread::JoinHandle<Summary>);
+
+/** Either kind of server, so that both run the same fixture. */
+pub trait Serve: Send + Sized + 'static {
+   fn serve(listener: TcpListener, router: Router, options: ServerOptions) -> Self;
+   fn address(&self) -> SocketAddr;
+   fn shutdown(&self) -> Shutdown;
+   fn run(self) -> Summary;
+}
+
+impl Serve for server::Server {
+   fn serve(listener: TcpListener, router: Router, options: ServerOptions) -> server::Server {
+      server::Server::new(listener, router, options)
+   }
+
+   fn address(&self) -> SocketAddr {
+      self.local_addr().unwrap()
+   }
+
+   fn shutdown(&self) -> Shutdown {
+      self.shutdown_handle().unwrap()
+   }
+
+   fn run(self) -> Summary {
+      server::Server::run(self)
+   }
+}
+
+impl Serve for reactor::Server {
+   fn serve(listener: TcpListener, router: Router, options: ServerOptions) -> reactor::Server {
+      reactor::Server::new(listener, router, options).unwrap()
+   }
+
+   fn address(&self) -> SocketAddr {
+      self.local_addr().unwrap()
+   }
+
+   fn shutdown(&self) -> Shutdown {
+      self.shutdown_handle().unwrap()
+   }
+
+   fn run(self) -> Summary {
+      reactor::Server::run(self)
+   }
+}
+
+/// Runs a server on a free port with routes `/`; `/slow`, which signals
+/// the returned receiver and then takes `slow` to answer; `/delayed`, whose
+/// response is delayed by `slow`; and `/panic`.
+pub fn start<S: Serve>(slow: Duration, options: ServerOptions) -> Started {
+   let (started, receiver) = mpsc::channel();
+   let started = Mutex::new(started);
+   let mut router = Router::new();
+
+   router
+      .get("/", |_, _| Response::new(200).body("hello"))
+      .get("/slow", move |_, _| {
+         started.lock().unwrap().send(()).unwrap();
+         thread::sleep(slow);
+
+         Response::new(200).body("slow")
+      })
+      .get("/delayed", move |_, _| Response::new(200).body("delayed").delay(slow))
+      .get("/panic", |_, _| panic!("handler failed"));
+
+   let server = S::serve(TcpListener::bind("127.0.0.1:0").unwrap(), router, options);
+   let address = server.address();
+   let shutdown = server.shutdown();
+
+   (address, shutdown, receiver, thread::spawn(move || server.run()))
+}
+
+/// Sends `GET path` on a connection of its own, and returns all that comes
+/// back.
+pub fn get(address: SocketAddr, path: &str) -> String {
+   let mut client = TcpStream::connect(address).unwrap();
+   let mut output = String::new();
+
+   write!(client, "GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path).unwrap();
+   client.read_to_string(&mut output).unwrap();
+
+   output
+}
@@ -0,0 +1,177 @@
+/* extern create usings */
+use http::{Request, Response};
+use router::Router;
+use server::{self, ConnectionOptions};
+
+use rustls::pki_types::pem::{self, PemObject};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+/* System includes */
+use std::io;
+
+use std::io::prelude::*;
+
+use std::net::TcpStream;
+use std::path::Path;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Loads a certificate chain and its private key from PEM files, for
+/// `ServerOptions::tls`.
+///
+/// ## Input:
+///
+/// * `cert` - The server's certificate, followed by any intermediate
+///   certificates.
+/// * `key` - The certificate's private key, in PKCS#1, PKCS#8 or SEC1 form.
+///
+/// ## Returns
+///
+/// * `Err(_)` - A file cannot be read, holds no certificate or key, or the
+///   key does not suit the certificate.
+pub fn load_config<P: AsRef<Path>, Q: AsRef<Path>>(cert: P, key: Q) -> io::Result<Arc<ServerConfig>> {
+   let certs = CertificateDer::pem_file_iter(cert.as_ref())
+      .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+      .map_err(pem_error)?;
+
+   if certs.is_empty() {
+      return Err(io::Error::new(
+         io::ErrorKind::InvalidData,
+         format!("No certificates in {}", cert.as_ref().display())
+      ));
+   }
+
+   let key = PrivateKeyDer::from_pem_file(key.as_ref()).map_err(pem_error)?;
+
+   let mut config = ServerConfig::builder()
+      .with_no_client_auth()
+      .with_single_cert(certs, key)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+   config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+   Ok(Arc::new(config))
+}
+
+fn pem_error(e: pem::Error) -> io::Error {
+   match e {
+      pem::Error::Io(e) => e,
+      e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+   }
+}
+
+/// Answers requests on a TLS connection, as `server::handle_connection`
+/// does on a plain one. The handshake happens as the first request is read.
+pub fn handle_connection(
+   stream: TcpStream,
+   config: Arc<ServerConfig>,
+   router: &Router,
+   options: &ConnectionOptions,
+   stopping: &AtomicBool
+) -> usize {
+   if let Err(e) = server::set_poll_timeout(&stream, options) {
+      warn!("Failed to set idle timeout: {}", e);
+
+      return 0;
+   }
+
+   let connection = match ServerConnection::new(config) {
+      Ok(connection) => connection,
+      Err(e) => {
+         error!("Failed to start a TLS session: {}", e);
+
+         return 0;
+      },
+   };
+
+   let mut stream = StreamOwned::new(connection, stream);
+   let served = server::serve(&mut stream, router, options, stopping);
+
+   // Tells the client the response was not cut short.
+   stream.conn.send_close_notify();
+   let _ = stream.flush();
+
+   served
+}
+
+/// Returns a Router that sends every request to the same URL over HTTPS.
+///
+/// ## Input:
+///
+/// * `https_port` - The port HTTPS is served on, left out of the URL if it
+///   is the default, 443.
+pub fn redirect(https_port: u16) -> Router {
+   let mut router = Router::new();
+
+   router.not_found(move |request, _| {
+      // 308 rather than 301, so that clients repeat a POST as a POST.
+      let status = match request.method.as_str() {
+         "GET" | "HEAD" => 301,
+         _ => 308,
+      };
+
+      Response::new(status).header("Location", &https_url(request, https_port))
+   });
+
+   router
+}
+
+fn https_url(request: &Request, https_port: u16) -> String {
+   let host = request.header("Host").map(without_port).unwrap_or("localhost");
+
+   // An absolute-form target names its own host; only the path is kept.
+   let path = if request.target.starts_with('/') { &request.target[..] } else { "/" };
+
+   match https_port {
+      443 => format!("https://{}{}", host, path),
+      port => format!("https://{}:{}{}", host, port, path),
+   }
+}
+
+/// Strips the port from a Host header, minding IPv6 literals.
+fn without_port(host: &str) -> &str {
+   match host.rfind(':') {
+      Some(colon) if !host[colon..].contains(']') => &host[..colon],
+      _ => host,
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use http::parse;
+
+   fn redirected(request: &str) -> Response {
+      let (request, _) = parse(request.as_bytes()).unwrap().unwrap();
+
+      redirect(8443).handle(&request)
+   }
+
+   fn location(response: &Response) -> &str {
+      &response.headers.iter().find(|(name, _)| name == "Location").unwrap().1
+   }
+
+   #[test]
+   fn redirects_to_the_https_port() {
+      let response = redirected("GET /a/b?c=d HTTP/1.1\r\nHost: example.com:8080\r\n\r\n");
+
+      assert_eq!(301, response.status);
+      assert_eq!("https://example.com:8443/a/b?c=d", location(&response));
+
+      let response = redirected("POST /form HTTP/1.1\r\nHost: [::1]:8080\r\nContent-Length: 0\r\n\r\n");
+
+      assert_eq!(308, response.status);
+      assert_eq!("https://[::1]:8443/form", location(&response));
+   }
+
+   #[test]
+   fn strips_ports_from_hosts() {
+      assert_eq!("example.com", without_port("example.com"));
+      assert_eq!("example.com", without_port("example.com:80"));
+      assert_eq!("[::1]", without_port("[::1]"));
+      assert_eq!("[::1]", without_port("[::1]:80"));
+   }
+}
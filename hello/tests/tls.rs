@@ -0,0 +1,210 @@
+/* extern crate definitions */
+extern crate hello;
+extern crate rcgen;
+extern crate rustls;
+extern crate tempfile;
+
+/* extern create usings */
+use hello::http::Response;
+use hello::router::Router;
+use hello::server::{Server, ServerOptions, Shutdown, Summary};
+use hello::tls;
+
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+use tempfile::TempDir;
+
+/* System includes */
+use std::fs;
+use std::io;
+use std::thread;
+
+use std::io::prelude::*;
+
+use std::convert::TryFrom;
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/** A self-signed certificate for localhost, written out as PEM files. */
+struct Certificate {
+   dir: TempDir,
+   der: CertificateDer<'static>,
+}
+
+impl Certificate {
+   fn new() -> Certificate {
+      let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+      let dir = TempDir::new().unwrap();
+
+      fs::write(dir.path().join("cert.pem"), generated.cert.pem()).unwrap();
+      fs::write(dir.path().join("key.pem"), generated.key_pair.serialize_pem()).unwrap();
+
+      Certificate {
+         dir,
+         der: generated.cert.der().clone(),
+      }
+   }
+
+   fn cert(&self) -> PathBuf {
+      self.dir.path().join("cert.pem")
+   }
+
+   fn key(&self) -> PathBuf {
+      self.dir.path().join("key.pem")
+   }
+
+   /// A client configuration trusting only this certificate.
+   fn client(&self) -> Arc<ClientConfig> {
+      let mut roots = RootCertStore::empty();
+
+      roots.add(self.der.clone()).unwrap();
+
+      let mut config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+
+      config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+      Arc::new(config)
+   }
+}
+
+/// Runs an HTTPS server on a free port that answers `/` with "hello".
+fn start(certificate: &Certificate) -> (SocketAddr, Shutdown, thread::JoinHandle<Summary>) {
+   let mut router = Router::new();
+
+   router.get("/", |_, _| Response::new(200).body("hello"));
+
+   let options = ServerOptions {
+      threads: 2,
+      tls: Some(tls::load_config(certificate.cert(), certificate.key()).unwrap()),
+      ..ServerOptions::default()
+   };
+
+   let server = Server::new(TcpListener::bind("127.0.0.1:0").unwrap(), router, options);
+   let address = server.local_addr().unwrap();
+   let shutdown = server.shutdown_handle().unwrap();
+
+   (address, shutdown, thread::spawn(move || server.run()))
+}
+
+fn connect(certificate: &Certificate, address: SocketAddr) -> StreamOwned<ClientConnection, TcpStream> {
+   let name = ServerName::try_from("localhost").unwrap();
+   let connection = ClientConnection::new(certificate.client(), name).unwrap();
+
+   StreamOwned::new(connection, TcpStream::connect(address).unwrap())
+}
+
+#[test]
+fn serves_https() {
+   let certificate = Certificate::new();
+   let (address, shutdown, server) = start(&certificate);
+
+   let mut client = connect(&certificate, address);
+   let mut output = String::new();
+
+   client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+   client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+   client.read_to_string(&mut output).unwrap();
+
+   assert!(output.starts_with("HTTP/1.1 200 OK"));
+   assert_eq!(2, output.matches("hello").count());
+   assert_eq!(Some(&b"http/1.1"[..]), client.conn.alpn_protocol());
+
+   shutdown.trigger();
+
+   let summary = server.join().unwrap();
+
+   assert_eq!((1, 2), (summary.connections, summary.requests));
+}
+
+#[test]
+fn plain_http_is_refused() {
+   let certificate = Certificate::new();
+   let (address, shutdown, server) = start(&certificate);
+
+   let mut plain = TcpStream::connect(address).unwrap();
+   let mut output = Vec::new();
+
+   plain.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+   plain.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+
+   // At most a TLS alert, never a response.
+   let _ = plain.read_to_end(&mut output);
+
+   assert!(!String::from_utf8_lossy(&output).contains("HTTP/1.1"));
+
+   let mut client = connect(&certificate, address);
+   let mut output = String::new();
+
+   client.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+   client.read_to_string(&mut output).unwrap();
+
+   assert!(output.ends_with("hello"));
+
+   shutdown.trigger();
+   server.join().unwrap();
+}
+
+#[test]
+fn untrusted_certificates_fail_the_handshake() {
+   let certificate = Certificate::new();
+   let other = Certificate::new();
+   let (address, shutdown, server) = start(&certificate);
+
+   let mut client = connect(&other, address);
+   let result = client.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").and_then(|_| {
+      client.read_to_string(&mut String::new())
+   });
+
+   assert_eq!(io::ErrorKind::InvalidData, result.unwrap_err().kind());
+
+   shutdown.trigger();
+   server.join().unwrap();
+}
+
+#[test]
+fn redirects_http_to_https() {
+   let options = ServerOptions {
+      threads: 1,
+      ..ServerOptions::default()
+   };
+
+   let server = Server::new(TcpListener::bind("127.0.0.1:0").unwrap(), tls::redirect(8443), options);
+   let address = server.local_addr().unwrap();
+   let shutdown = server.shutdown_handle().unwrap();
+   let server = thread::spawn(move || server.run());
+
+   let mut client = TcpStream::connect(address).unwrap();
+   let mut output = String::new();
+
+   client.write_all(b"GET /page?q=1 HTTP/1.1\r\nHost: localhost:8080\r\nConnection: close\r\n\r\n").unwrap();
+   client.read_to_string(&mut output).unwrap();
+
+   assert!(output.starts_with("HTTP/1.1 301 Moved Permanently"));
+   assert!(output.contains("Location: https://localhost:8443/page?q=1\r\n"));
+
+   shutdown.trigger();
+   server.join().unwrap();
+}
+
+#[test]
+fn bad_pem_files_are_reported() {
+   let certificate = Certificate::new();
+   let missing = certificate.dir.path().join("missing.pem");
+
+   let error = tls::load_config(&missing, certificate.key()).unwrap_err();
+
+   assert_eq!(io::ErrorKind::NotFound, error.kind());
+
+   // A key where the certificate should be, and the other way round.
+   let error = tls::load_config(certificate.key(), certificate.key()).unwrap_err();
+
+   assert_eq!(io::ErrorKind::InvalidData, error.kind());
+
+   let error = tls::load_config(certificate.cert(), certificate.cert()).unwrap_err();
+
+   assert_eq!(io::ErrorKind::InvalidData, error.kind());
+}
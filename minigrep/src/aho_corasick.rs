@@ -0,0 +1,339 @@
+use std::collections::VecDeque;
+
+use casefold;
+
+/// An occurrence of a pattern in a haystack. `start` and `end` are byte
+/// offsets into the haystack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatternMatch {
+   pub pattern: usize,
+   pub start: usize,
+   pub end: usize,
+}
+
+const ROOT: usize = 0;
+
+struct Node {
+   /// Outgoing edges of the trie, sorted by character.
+   next: Vec<(char, usize)>,
+   /// Longest proper suffix of this node that is also a trie node.
+   fail: usize,
+   /// Closest node along the fail chain that ends a pattern.
+   output: Option<usize>,
+   /// Pattern ending at this node, if any.
+   pattern: Option<usize>,
+   /// Length in characters of the path from the root.
+   depth: usize,
+}
+
+impl Node {
+   fn new(depth: usize) -> Node {
+      Node {
+         next: Vec::new(),
+         fail: ROOT,
+         output: None,
+         pattern: None,
+         depth,
+      }
+   }
+
+   fn child(&self, c: char) -> Option<usize> {
+      self.next.binary_search_by(|&(k, _)| k.cmp(&c))
+         .ok()
+         .map(|i| self.next[i].1)
+   }
+}
+
+/// Aho–Corasick automaton finding any number of patterns in a single pass
+/// over the haystack.
+///
+/// When built with `fold` set, patterns and haystack are compared by their
+/// Unicode case folding (see `casefold`).
+pub struct AhoCorasick {
+   nodes: Vec<Node>,
+   fold: bool,
+}
+
+impl AhoCorasick {
+   /// Builds the automaton for `patterns`. The index of a pattern in the
+   /// slice is the `pattern` reported in its matches; duplicates report the
+   /// first index only.
+   pub fn new<S: AsRef<str>>(patterns: &[S], fold: bool) -> AhoCorasick {
+      let mut ac = AhoCorasick {
+         nodes: vec![Node::new(0)],
+         fold,
+      };
+
+      for (id, pattern) in patterns.iter().enumerate() {
+         let chars: Vec<char> = if fold {
+            casefold::fold_str(pattern.as_ref())
+         } else {
+            pattern.as_ref().chars().collect()
+         };
+
+         let mut state = ROOT;
+
+         for c in chars {
+            state = match ac.nodes[state].child(c) {
+               Some(child) => child,
+               None => ac.add_child(state, c),
+            };
+         }
+
+         if ac.nodes[state].pattern.is_none() {
+            ac.nodes[state].pattern = Some(id);
+         }
+      }
+
+      ac.build_links();
+
+      ac
+   }
+
+   fn add_child(&mut self, state: usize, c: char) -> usize {
+      let child = self.nodes.len();
+      let depth = self.nodes[state].depth + 1;
+
+      self.nodes.push(Node::new(depth));
+
+      let next = &mut self.nodes[state].next;
+      let pos = next.binary_search_by(|&(k, _)| k.cmp(&c)).unwrap_err();
+      next.insert(pos, (c, child));
+
+      child
+   }
+
+   /// Computes the fail and output links breadth first, so that every node's
+   /// fail target is finished before its children are visited.
+   fn build_links(&mut self) {
+      let mut queue: VecDeque<usize> = self.nodes[ROOT].next.iter()
+         .map(|&(_, child)| child)
+         .collect();
+
+      while let Some(state) = queue.pop_front() {
+         for i in 0..self.nodes[state].next.len() {
+            let (c, child) = self.nodes[state].next[i];
+
+            let mut fail = self.nodes[state].fail;
+            let target = loop {
+               if let Some(n) = self.nodes[fail].child(c) {
+                  break n;
+               }
+
+               if fail == ROOT {
+                  break ROOT;
+               }
+
+               fail = self.nodes[fail].fail;
+            };
+
+            self.nodes[child].fail = target;
+            self.nodes[child].output = if target != ROOT && self.nodes[target].pattern.is_some() {
+               Some(target)
+            } else {
+               self.nodes[target].output
+            };
+
+            queue.push_back(child);
+         }
+      }
+   }
+
+   fn next_state(&self, mut state: usize, c: char) -> usize {
+      loop {
+         if let Some(next) = self.nodes[state].child(c) {
+            return next;
+         }
+
+         if state == ROOT {
+            return ROOT;
+         }
+
+         state = self.nodes[state].fail;
+      }
+   }
+
+   /// Calls `f` for every occurrence of every pattern in `haystack`,
+   /// including overlapping ones, ordered by end offset.
+   pub fn for_each_match<F: FnMut(PatternMatch)>(&self, haystack: &str, mut f: F) {
+      if let Some(pattern) = self.nodes[ROOT].pattern {
+         f(PatternMatch { pattern, start: 0, end: 0 });
+      }
+
+      let mut state = ROOT;
+
+      for (offset, c) in haystack.char_indices() {
+         if self.fold {
+            for folded in casefold::fold(c) {
+               state = self.next_state(state, folded);
+            }
+         } else {
+            state = self.next_state(state, c);
+         }
+
+         let end = offset + c.len_utf8();
+
+         let mut node = if state != ROOT && self.nodes[state].pattern.is_some() {
+            Some(state)
+         } else {
+            self.nodes[state].output
+         };
+
+         while let Some(n) = node {
+            if let Some(start) = self.match_start(haystack, end, self.nodes[n].depth) {
+               f(PatternMatch {
+                  pattern: self.nodes[n].pattern.unwrap(),
+                  start,
+                  end,
+               });
+            }
+
+            node = self.nodes[n].output;
+         }
+      }
+   }
+
+   /// Returns the non-overlapping matches in `haystack`, preferring the
+   /// leftmost and then the longest match.
+   pub fn find_all(&self, haystack: &str) -> Vec<PatternMatch> {
+      let mut all = Vec::new();
+      self.for_each_match(haystack, |m| all.push(m));
+
+      leftmost_longest(all)
+   }
+
+   /// Finds where a match of `len` (folded) characters ending at byte `end`
+   /// starts, or `None` if it would start inside the folding of a character.
+   fn match_start(&self, haystack: &str, end: usize, len: usize) -> Option<usize> {
+      let mut matched = 0;
+
+      for (offset, c) in haystack[..end].char_indices().rev() {
+         matched += if self.fold { casefold::fold(c).count() } else { 1 };
+
+         if matched == len {
+            return Some(offset);
+         }
+
+         if matched > len {
+            return None;
+         }
+      }
+
+      None
+   }
+}
+
+/// Reduces possibly overlapping matches to the non-overlapping ones, preferring
+/// the leftmost and then the longest match.
+pub fn leftmost_longest(mut all: Vec<PatternMatch>) -> Vec<PatternMatch> {
+   all.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+
+   let mut matches: Vec<PatternMatch> = Vec::new();
+
+   for m in all {
+      if matches.last().is_none_or(|last| m.start >= last.end) {
+         matches.push(m);
+      }
+   }
+
+   matches
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use std::ops::Range;
+
+   fn matches(patterns: &[&str], fold: bool, haystack: &str) -> Vec<(usize, usize, usize)> {
+      let mut found = Vec::new();
+
+      AhoCorasick::new(patterns, fold).for_each_match(haystack, |m| {
+         found.push((m.pattern, m.start, m.end));
+      });
+
+      found
+   }
+
+   #[test]
+   fn overlapping_patterns() {
+      assert_eq!(
+         vec![(1, 1, 4), (0, 2, 4), (2, 2, 6)],
+         matches(&["he", "she", "hers"], false, "ushers")
+      );
+   }
+
+   #[test]
+   fn case_folded_offsets() {
+      assert_eq!(
+         vec![(0, 4, 11), (1, 8, 11)],
+         matches(&["STRASSE", "sse"], true, "Die Straße")
+      );
+   }
+
+   fn first(needle: &str, haystack: &str) -> Option<Range<usize>> {
+      AhoCorasick::new(&[needle], true)
+         .find_all(haystack)
+         .first()
+         .map(|m| m.start..m.end)
+   }
+
+   #[test]
+   fn german_sharp_s() {
+      assert_eq!(Some(0..7), first("STRASSE", "Straße"));
+      assert_eq!(Some(0..7), first("straße", "STRASSE"));
+      assert_eq!(Some(2..10), first("straße", "- STRAẞE"));
+      assert_eq!(Some(4..6), first("ss", "Straße"));
+      assert_eq!(None, first("s", "ß"));
+   }
+
+   #[test]
+   fn greek_sigma() {
+      let line = "Σοφός";
+
+      assert_eq!(Some(0..line.len()), first("ΣΟΦΌΣ", line));
+      assert_eq!(Some(0..line.len()), first("σοφόσ", line));
+      assert_eq!(Some(6..10), first("ΌΣ", line));
+   }
+
+   #[test]
+   fn turkish_i() {
+      // 'İ' folds to "i\u{307}", so it only matches a dotted i.
+      assert_eq!(Some(3..12), first("İstanbul", "in İSTANBUL"));
+      assert_eq!(Some(0..10), first("İstanbul", "i\u{307}stanbul"));
+      assert_eq!(Some(0..1), first("I", "i\u{307}"));
+      assert_eq!(None, first("istanbul", "ıstanbul"));
+      assert_eq!(None, first("ı", "I"));
+   }
+
+   #[test]
+   fn offsets_are_in_original_line() {
+      // 'Ⱥ' (2 bytes) lowercases to 'ⱥ' (3 bytes).
+      assert_eq!(Some(3..6), first("ⱥb", "aȺȺb"));
+      assert_eq!(Some(0..0), first("", "anything"));
+   }
+
+   #[test]
+   fn leftmost_longest() {
+      let found: Vec<(usize, usize)> = AhoCorasick::new(&["b", "abc", "ab", "cd"], false)
+         .find_all("abcd")
+         .iter()
+         .map(|m| (m.start, m.end))
+         .collect();
+
+      assert_eq!(vec![(0, 3)], found);
+   }
+
+   #[test]
+   fn many_patterns() {
+      let patterns: Vec<String> = (0..10_000).map(|i| format!("p{}x", i)).collect();
+      let ac = AhoCorasick::new(&patterns, false);
+
+      let found: Vec<usize> = ac.find_all("xx p42x p9999x p10000x")
+         .iter()
+         .map(|m| m.pattern)
+         .collect();
+
+      assert_eq!(vec![42, 9999], found);
+   }
+}
@@ -0,0 +1,124 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::ops::Range;
+
+const FILENAME: &str = "35";
+const LINE_NUMBER: &str = "32";
+const MATCH: &str = "1;31";
+
+/// When to color the output, as chosen with `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+   Auto,
+   Always,
+   Never,
+}
+
+impl ColorChoice {
+   pub fn parse(s: &str) -> Option<ColorChoice> {
+      match s {
+         "auto" => Some(ColorChoice::Auto),
+         "always" => Some(ColorChoice::Always),
+         "never" => Some(ColorChoice::Never),
+         _ => None,
+      }
+   }
+
+   /// `Auto` colors only when stdout is a terminal and `NO_COLOR` is unset
+   /// or empty.
+   pub fn enabled(self) -> bool {
+      match self {
+         ColorChoice::Always => true,
+         ColorChoice::Never => false,
+         ColorChoice::Auto => {
+            let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+
+            !no_color && io::stdout().is_terminal()
+         },
+      }
+   }
+}
+
+/// Wraps the parts of an output line in ANSI color escapes, or passes them
+/// through untouched when colors are off.
+pub struct Colors {
+   enabled: bool,
+}
+
+impl Colors {
+   pub fn new(choice: ColorChoice) -> Colors {
+      Colors { enabled: choice.enabled() }
+   }
+
+   pub fn filename(&self, filename: &str) -> String {
+      self.paint(FILENAME, filename)
+   }
+
+   pub fn line_number(&self, line_number: usize) -> String {
+      self.paint(LINE_NUMBER, &line_number.to_string())
+   }
+
+   /// Returns `line` with the byte ranges in `spans` highlighted. The spans
+   /// must be sorted and must not overlap.
+   pub fn highlight<I: IntoIterator<Item = Range<usize>>>(&self, line: &str, spans: I) -> String {
+      if !self.enabled {
+         return line.to_string();
+      }
+
+      let mut highlighted = String::with_capacity(line.len());
+      let mut last = 0;
+
+      for span in spans {
+         if span.start == span.end {
+            continue;
+         }
+
+         highlighted.push_str(&line[last..span.start]);
+         highlighted.push_str(&self.paint(MATCH, &line[span.clone()]));
+         last = span.end;
+      }
+
+      highlighted.push_str(&line[last..]);
+
+      highlighted
+   }
+
+   fn paint(&self, color: &str, text: &str) -> String {
+      if self.enabled {
+         format!("\x1b[{}m{}\x1b[0m", color, text)
+      } else {
+         text.to_string()
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn highlights_spans() {
+      let colors = Colors { enabled: true };
+
+      assert_eq!(
+         "safe, \x1b[1;31mfast\x1b[0m, pro\x1b[1;31mduct\x1b[0mive.",
+         colors.highlight("safe, fast, productive.", vec![6..10, 15..19])
+      );
+      assert_eq!("\x1b[32m42\x1b[0m", colors.line_number(42));
+   }
+
+   #[test]
+   fn disabled_passes_through() {
+      let colors = Colors::new(ColorChoice::Never);
+
+      assert_eq!("safe, fast", colors.highlight("safe, fast", vec![0..4, 6..10]));
+      assert_eq!("poem.txt", colors.filename("poem.txt"));
+   }
+
+   #[test]
+   fn parses_choice() {
+      assert_eq!(Some(ColorChoice::Always), ColorChoice::parse("always"));
+      assert_eq!(None, ColorChoice::parse("sometimes"));
+      assert!(ColorChoice::Always.enabled());
+   }
+}
@@ -0,0 +1,236 @@
+use std::cmp::Ordering;
+use std::mem;
+
+use casefold;
+
+const SCORE_MATCH: i32 = 16;
+const SCORE_GAP_START: i32 = -3;
+const SCORE_GAP_EXTENSION: i32 = -1;
+
+/// Bonus for matching the first character of a word.
+const BONUS_BOUNDARY: i32 = 8;
+/// Bonus for matching an uppercase letter following a lowercase one.
+const BONUS_CAMEL: i32 = 7;
+/// Bonus for matching right after the previous query character.
+const BONUS_CONSECUTIVE: i32 = 4;
+/// The bonus of the first query character counts this many times.
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+
+/// Most query characters times line characters scored. Longer lines, such
+/// as those of minified files, are skipped rather than tracked in memory.
+const MAX_CELLS: usize = 1 << 20;
+
+/// A line matching a fuzzy query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+   pub score: i32,
+   /// Byte offsets of the line characters matched by the query characters.
+   pub positions: Vec<usize>,
+}
+
+/// Scores lines against a query whose characters must appear in the line in
+/// order, though not necessarily next to each other, in the manner of fzf.
+///
+/// Every matched character scores, more so on word boundaries and right
+/// after the previous match, while gaps between matches cost a little.
+pub struct FuzzyMatcher {
+   query: Vec<char>,
+   case_sensitive: bool,
+}
+
+impl FuzzyMatcher {
+   pub fn new(query: &str, case_sensitive: bool) -> FuzzyMatcher {
+      FuzzyMatcher {
+         query: query.chars().collect(),
+         case_sensitive,
+      }
+   }
+
+   /// Returns the best scoring way to match the query in `line`, or `None`
+   /// when the query is not a subsequence of it or the line is too long to
+   /// score.
+   pub fn score(&self, line: &str) -> Option<FuzzyMatch> {
+      let chars: Vec<(usize, char)> = line.char_indices().collect();
+      let (n, m) = (self.query.len(), chars.len());
+
+      if n == 0 {
+         return Some(FuzzyMatch { score: 0, positions: Vec::new() });
+      }
+
+      if n > m || n * m > MAX_CELLS {
+         return None;
+      }
+
+      let bonuses: Vec<i32> = (0..m).map(|j| bonus(&chars, j)).collect();
+
+      // row[j]: best score with query[..=i] matched and query[i] on chars[j],
+      // with `prev` holding the same for query[i - 1]. from[i * m + j]: where
+      // query[i - 1] was matched for that score.
+      let mut prev: Vec<Option<i32>> = vec![None; m];
+      let mut row: Vec<Option<i32>> = vec![None; m];
+      let mut from = vec![0u32; n * m];
+
+      for (i, &q) in self.query.iter().enumerate() {
+         // Best score of query[i - 1] on an earlier character, already
+         // charged for the gap up to the current one.
+         let mut gapped: Option<(i32, usize)> = None;
+
+         row.iter_mut().for_each(|score| *score = None);
+
+         for j in i..m {
+            if i > 0 && j >= 2 {
+               let extended = gapped.map(|(score, k)| (score + SCORE_GAP_EXTENSION, k));
+               let started = prev[j - 2].map(|score| (score + SCORE_GAP_START, j - 2));
+
+               gapped = best(extended, started);
+            }
+
+            if !self.eq(q, chars[j].1) {
+               continue;
+            }
+
+            let base = if i == 0 {
+               SCORE_MATCH + bonuses[j] * BONUS_FIRST_CHAR_MULTIPLIER
+            } else {
+               SCORE_MATCH + bonuses[j]
+            };
+
+            if i == 0 {
+               row[j] = Some(base);
+               continue;
+            }
+
+            let consecutive = prev[j - 1].map(|score| (score + BONUS_CONSECUTIVE, j - 1));
+
+            if let Some((score, k)) = best(consecutive, gapped) {
+               row[j] = Some(score + base);
+               from[i * m + j] = k as u32;
+            }
+         }
+
+         mem::swap(&mut prev, &mut row);
+      }
+
+      let (score, mut j) = (0..m)
+         .filter_map(|j| prev[j].map(|score| (score, j)))
+         .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))?;
+
+      let mut positions = vec![0; n];
+
+      for i in (0..n).rev() {
+         positions[i] = chars[j].0;
+         j = from[i * m + j] as usize;
+      }
+
+      Some(FuzzyMatch { score, positions })
+   }
+
+   /// Compares characters, by their case folding unless case sensitive.
+   fn eq(&self, q: char, c: char) -> bool {
+      q == c || (!self.case_sensitive && casefold::fold(q).eq(casefold::fold(c)))
+   }
+}
+
+fn best(a: Option<(i32, usize)>, b: Option<(i32, usize)>) -> Option<(i32, usize)> {
+   match (a, b) {
+      (Some(a), Some(b)) => Some(if b.0 > a.0 { b } else { a }),
+      (a, None) => a,
+      (None, b) => b,
+   }
+}
+
+fn bonus(chars: &[(usize, char)], j: usize) -> i32 {
+   let c = chars[j].1;
+
+   if j == 0 {
+      return BONUS_BOUNDARY;
+   }
+
+   let prev = chars[j - 1].1;
+
+   if c.is_alphanumeric() && !prev.is_alphanumeric() {
+      BONUS_BOUNDARY
+   } else if c.is_uppercase() && prev.is_lowercase() {
+      BONUS_CAMEL
+   } else {
+      0
+   }
+}
+
+/// Sorts scored results best first: by score, then shorter lines, keeping
+/// the input order otherwise.
+pub fn rank<T, F>(results: &mut [(FuzzyMatch, T)], len: F)
+   where
+      F: Fn(&T) -> usize
+{
+   results.sort_by(|a, b| match b.0.score.cmp(&a.0.score) {
+      Ordering::Equal => len(&a.1).cmp(&len(&b.1)),
+      order => order,
+   });
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn subsequence_positions() {
+      let matcher = FuzzyMatcher::new("fbr", true);
+
+      assert_eq!(vec![0, 4, 6], matcher.score("foo bar").unwrap().positions);
+      assert_eq!(None, matcher.score("bar foo"));
+   }
+
+   #[test]
+   fn prefers_boundaries_and_consecutive_matches() {
+      let matcher = FuzzyMatcher::new("mg", false);
+
+      let spread = matcher.score("image").unwrap();
+      let boundaries = matcher.score("mini grep").unwrap();
+
+      assert!(boundaries.score > spread.score);
+      assert_eq!(vec![0, 5], boundaries.positions);
+
+      let matcher = FuzzyMatcher::new("grep", false);
+
+      assert_eq!(vec![5, 6, 7, 8], matcher.score("gr e grep").unwrap().positions);
+   }
+
+   #[test]
+   fn case_insensitive() {
+      let matcher = FuzzyMatcher::new("TP", false);
+
+      assert_eq!(vec![0, 6], matcher.score("ThreadPool").unwrap().positions);
+
+      // Final sigma folds to sigma; it has no uppercase form of its own.
+      let matcher = FuzzyMatcher::new("ΣΑΣ", false);
+
+      assert_eq!(vec![0, 2, 4], matcher.score("σας").unwrap().positions);
+   }
+
+   #[test]
+   fn skips_overlong_lines() {
+      let matcher = FuzzyMatcher::new("ab", true);
+      let long = format!("a{}b", " ".repeat(MAX_CELLS / 2));
+      let short = format!("a{}b", " ".repeat(MAX_CELLS / 4));
+
+      assert_eq!(None, matcher.score(&long));
+      assert!(matcher.score(&short).is_some());
+   }
+
+   #[test]
+   fn ranks_best_first() {
+      let matcher = FuzzyMatcher::new("tp", false);
+      let lines = ["the pool", "tape", "ThreadPool", "nothing"];
+
+      let mut results: Vec<(FuzzyMatch, &str)> = lines.iter()
+         .filter_map(|&line| matcher.score(line).map(|m| (m, line)))
+         .collect();
+
+      rank(&mut results, |line| line.len());
+
+      let ranked: Vec<&str> = results.iter().map(|&(_, line)| line).collect();
+
+      assert_eq!(vec!["the pool", "ThreadPool", "tape"], ranked);
+   }
+}
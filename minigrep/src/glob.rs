@@ -0,0 +1,191 @@
+/// A shell-style glob over '/' separated paths.
+///
+/// `*` and `?` match within a path component, `**` matches across them and
+/// `[a-z]`/`[!a-z]` match character classes. A glob without a '/' matches the
+/// file name; one with a '/' matches the path, at any depth unless it starts
+/// with '/'.
+#[derive(Debug, Clone)]
+pub struct Glob {
+   pattern: Vec<char>,
+   whole_path: bool,
+   anchored: bool,
+}
+
+impl Glob {
+   pub fn new(pattern: &str) -> Glob {
+      let anchored = pattern.starts_with('/');
+      let pattern = pattern.trim_start_matches('/');
+
+      Glob {
+         pattern: pattern.chars().collect(),
+         whole_path: anchored || pattern.contains('/'),
+         anchored,
+      }
+   }
+
+   /// Matches `path`, which is relative to the searched directory.
+   pub fn is_match(&self, path: &str) -> bool {
+      let path: Vec<char> = path.chars().collect();
+
+      if !self.whole_path {
+         let name = match path.iter().rposition(|&c| c == '/') {
+            Some(slash) => &path[slash + 1..],
+            None => &path[..],
+         };
+
+         return matches(&self.pattern, name);
+      }
+
+      if self.anchored {
+         return matches(&self.pattern, &path);
+      }
+
+      matches(&self.pattern, &path) || path.iter()
+         .enumerate()
+         .filter(|&(_, &c)| c == '/')
+         .any(|(slash, _)| matches(&self.pattern, &path[slash + 1..]))
+   }
+
+   /// Whether the glob matches everything below the directory `path`, as
+   /// `target/**` does for `target`.
+   pub fn matches_all_below(&self, path: &str) -> bool {
+      let len = self.pattern.len();
+
+      if len < 3 || self.pattern[len - 3..] != ['/', '*', '*'] {
+         return false;
+      }
+
+      let dir = Glob {
+         pattern: self.pattern[..len - 3].to_vec(),
+         whole_path: true,
+         anchored: self.anchored,
+      };
+
+      dir.is_match(path)
+   }
+}
+
+fn matches(pattern: &[char], text: &[char]) -> bool {
+   match pattern.first() {
+      None => text.is_empty(),
+      Some(&'*') if pattern.get(1) == Some(&'*') => {
+         let rest = &pattern[2..];
+
+         if let Some(rest) = rest.strip_prefix(&['/']) {
+            // "**/" matches any number of whole components, including none.
+            matches(rest, text) || text.iter()
+               .enumerate()
+               .filter(|&(_, &c)| c == '/')
+               .any(|(slash, _)| matches(rest, &text[slash + 1..]))
+         } else {
+            (0..=text.len()).any(|i| matches(rest, &text[i..]))
+         }
+      },
+      Some(&'*') => {
+         for i in 0..=text.len() {
+            if matches(&pattern[1..], &text[i..]) {
+               return true;
+            }
+
+            if i < text.len() && text[i] == '/' {
+               break;
+            }
+         }
+
+         false
+      },
+      Some(&'?') => match text.first() {
+         Some(&c) if c != '/' => matches(&pattern[1..], &text[1..]),
+         _ => false,
+      },
+      Some(&'[') => match (class(&pattern[1..]), text.first()) {
+         (Some((set, len)), Some(&c)) => {
+            c != '/' && set.contains(c) && matches(&pattern[len + 1..], &text[1..])
+         },
+         (None, Some(&'[')) => matches(&pattern[1..], &text[1..]),
+         _ => false,
+      },
+      Some(&p) => match text.first() {
+         Some(&c) if c == p => matches(&pattern[1..], &text[1..]),
+         _ => false,
+      },
+   }
+}
+
+/// A `[...]` character class.
+struct Class {
+   negated: bool,
+   ranges: Vec<(char, char)>,
+}
+
+impl Class {
+   fn contains(&self, c: char) -> bool {
+      self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+   }
+}
+
+/// Parses the class following a '[', returning it with the number of
+/// characters it took including the closing ']'.
+fn class(pattern: &[char]) -> Option<(Class, usize)> {
+   let negated = pattern.first() == Some(&'!') || pattern.first() == Some(&'^');
+   let mut i = if negated { 1 } else { 0 };
+   let mut ranges = Vec::new();
+
+   // A ']' right at the start is part of the class.
+   let start = i;
+
+   while i < pattern.len() {
+      let c = pattern[i];
+
+      if c == ']' && i > start {
+         return Some((Class { negated, ranges }, i + 1));
+      }
+
+      if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+         ranges.push((c, pattern[i + 2]));
+         i += 3;
+      } else {
+         ranges.push((c, c));
+         i += 1;
+      }
+   }
+
+   None
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn file_name_globs() {
+      let glob = Glob::new("*.toml");
+
+      assert!(glob.is_match("Cargo.toml"));
+      assert!(glob.is_match("hello/Cargo.toml"));
+      assert!(!glob.is_match("hello/Cargo.lock"));
+      assert!(Glob::new("lib.r?").is_match("src/lib.rs"));
+      assert!(Glob::new("[a-c]*.rs").is_match("src/casefold.rs"));
+      assert!(!Glob::new("[!a-c]*.rs").is_match("src/casefold.rs"));
+   }
+
+   #[test]
+   fn path_globs() {
+      let glob = Glob::new("target/**");
+
+      assert!(glob.is_match("target/debug/minigrep"));
+      assert!(glob.is_match("hello/target/debug/hello"));
+      assert!(!glob.is_match("hello/src/target.rs"));
+      assert!(glob.matches_all_below("hello/target"));
+      assert!(!glob.matches_all_below("hello/src"));
+
+      let glob = Glob::new("/src/*.rs");
+
+      assert!(glob.is_match("src/lib.rs"));
+      assert!(!glob.is_match("minigrep/src/lib.rs"));
+      assert!(!glob.is_match("src/bin/main.rs"));
+
+      assert!(Glob::new("src/**/*.rs").is_match("src/bin/main.rs"));
+      assert!(Glob::new("src/**/*.rs").is_match("src/main.rs"));
+   }
+}
@@ -0,0 +1,525 @@
+use std::collections::HashSet;
+use std::fs::{self, Metadata};
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::process;
+use std::str;
+use std::time::UNIX_EPOCH;
+
+use casefold;
+use input;
+use walk::{self, Filter};
+
+/// Name of the index file, kept in the indexed directory. Being hidden, it
+/// is not searched itself.
+pub const INDEX_FILE: &str = ".minigrep-index";
+
+const MAGIC: &[u8] = b"minigrep-index 1\n";
+
+/// The trigrams of one indexed file, with what the file looked like when it
+/// was read.
+struct Entry {
+   /// Path relative to the indexed directory, '/' separated.
+   path: String,
+   modified: (u64, u32),
+   len: u64,
+   /// Sorted trigrams of the case folded contents, as 24 bit numbers.
+   trigrams: Vec<u32>,
+}
+
+impl Entry {
+   fn is_fresh(&self, metadata: &Metadata) -> bool {
+      self.len == metadata.len() && Some(self.modified) == modified(metadata)
+   }
+}
+
+/// A trigram index over the files of a directory, narrowing down the files
+/// that can contain a literal pattern.
+pub struct Index {
+   /// Entries sorted by path.
+   entries: Vec<Entry>,
+}
+
+/// Counts of a `build`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BuildStats {
+   pub files: usize,
+   /// Files read because they were new or changed since the last build.
+   pub updated: usize,
+}
+
+impl Index {
+   /// Loads the index of `dir`, or `None` if there is none.
+   pub fn load(dir: &Path) -> io::Result<Option<Index>> {
+      let bytes = match fs::read(dir.join(INDEX_FILE)) {
+         Ok(bytes) => bytes,
+         Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+         Err(e) => return Err(e),
+      };
+
+      Index::decode(&bytes).map(Some)
+   }
+
+   /// Indexes every file below `dir`, reusing the entries of the previous
+   /// index for files whose size and modification time did not change.
+   pub fn build(dir: &Path) -> io::Result<BuildStats> {
+      // An unreadable old index is simply rebuilt from scratch.
+      let mut old = Index::load(dir).unwrap_or(None);
+
+      let mut files = Vec::new();
+      walk::walk(dir, &Filter::new(&[], &[], &[]).unwrap(), &mut files)?;
+
+      let mut entries = Vec::with_capacity(files.len());
+      let mut updated = 0;
+
+      for file in files {
+         let path = relative_path(dir, &file);
+         // A file left out of the index is searched like any file the
+         // index does not know.
+         let metadata = match fs::metadata(&file) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+               walk::skip(&file, &e);
+
+               continue;
+            },
+         };
+
+         let reused = old.as_mut()
+            .and_then(|old| old.take_fresh(&path, &metadata));
+
+         let entry = match reused {
+            Some(entry) => entry,
+            None => {
+               let trigrams = match file_trigrams(&file) {
+                  Ok(trigrams) => trigrams,
+                  Err(e) => {
+                     walk::skip(&file, &e);
+
+                     continue;
+                  },
+               };
+
+               updated += 1;
+
+               Entry {
+                  trigrams,
+                  modified: modified(&metadata).unwrap_or((0, 0)),
+                  len: metadata.len(),
+                  path,
+               }
+            },
+         };
+
+         entries.push(entry);
+      }
+
+      entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+      let stats = BuildStats { files: entries.len(), updated };
+
+      Index { entries }.save(dir)?;
+
+      Ok(stats)
+   }
+
+   fn take_fresh(&mut self, path: &str, metadata: &Metadata) -> Option<Entry> {
+      let i = self.position(path)?;
+      let entry = &mut self.entries[i];
+
+      if !entry.is_fresh(metadata) {
+         return None;
+      }
+
+      Some(Entry {
+         path: entry.path.clone(),
+         modified: entry.modified,
+         len: entry.len,
+         trigrams: mem::take(&mut entry.trigrams),
+      })
+   }
+
+   fn position(&self, path: &str) -> Option<usize> {
+      self.entries.binary_search_by(|entry| entry.path.as_str().cmp(path)).ok()
+   }
+
+   fn entry(&self, path: &str) -> Option<&Entry> {
+      self.position(path).map(|i| &self.entries[i])
+   }
+
+   /// Whether the file at `path` below `dir` may contain a match of `query`.
+   /// Returns `None` when the index does not know the file as it is now, in
+   /// which case the file has to be searched anyway.
+   pub fn may_match(&self, dir: &Path, path: &Path, query: &Query) -> Option<bool> {
+      let metadata = fs::metadata(path).ok()?;
+      let entry = self.entry(&relative_path(dir, path))?;
+
+      if !entry.is_fresh(&metadata) {
+         return None;
+      }
+
+      Some(query.patterns.iter().any(|trigrams| {
+         trigrams.iter().all(|t| entry.trigrams.binary_search(t).is_ok())
+      }))
+   }
+
+   fn save(&self, dir: &Path) -> io::Result<()> {
+      let path = dir.join(INDEX_FILE);
+      let tmp = dir.join(format!("{}.{}.tmp", INDEX_FILE, process::id()));
+
+      fs::write(&tmp, self.encode())?;
+      fs::rename(&tmp, &path).inspect_err(|_| {
+         let _ = fs::remove_file(&tmp);
+      })
+   }
+
+   fn encode(&self) -> Vec<u8> {
+      let mut bytes = MAGIC.to_vec();
+
+      put_u64(&mut bytes, self.entries.len() as u64);
+
+      for entry in &self.entries {
+         put_u64(&mut bytes, entry.path.len() as u64);
+         bytes.extend_from_slice(entry.path.as_bytes());
+         put_u64(&mut bytes, entry.modified.0);
+         put_u64(&mut bytes, u64::from(entry.modified.1));
+         put_u64(&mut bytes, entry.len);
+         put_u64(&mut bytes, entry.trigrams.len() as u64);
+
+         for &trigram in &entry.trigrams {
+            bytes.extend_from_slice(&trigram.to_be_bytes()[1..]);
+         }
+      }
+
+      bytes
+   }
+
+   fn decode(bytes: &[u8]) -> io::Result<Index> {
+      let corrupt = || io::Error::new(io::ErrorKind::InvalidData, "corrupt index");
+
+      let mut bytes = bytes.strip_prefix(MAGIC).ok_or_else(corrupt)?;
+      let mut entries = Vec::new();
+
+      for _ in 0..take_u64(&mut bytes).ok_or_else(corrupt)? {
+         let len = take_u64(&mut bytes).ok_or_else(corrupt)? as usize;
+         let path = take(&mut bytes, len).ok_or_else(corrupt)?;
+         let path = String::from_utf8(path.to_vec()).map_err(|_| corrupt())?;
+
+         let secs = take_u64(&mut bytes).ok_or_else(corrupt)?;
+         let nanos = take_u64(&mut bytes).ok_or_else(corrupt)? as u32;
+         let len = take_u64(&mut bytes).ok_or_else(corrupt)?;
+
+         let count = take_u64(&mut bytes).ok_or_else(corrupt)? as usize;
+         let packed = take(&mut bytes, count.checked_mul(3).ok_or_else(corrupt)?).ok_or_else(corrupt)?;
+         let trigrams = packed.chunks(3)
+            .map(|t| u32::from_be_bytes([0, t[0], t[1], t[2]]))
+            .collect();
+
+         entries.push(Entry { path, modified: (secs, nanos), len, trigrams });
+      }
+
+      Ok(Index { entries })
+   }
+}
+
+/// The trigrams a file must contain to match a search.
+pub struct Query {
+   /// Trigrams of each pattern; a file has to contain all the trigrams of
+   /// any one pattern.
+   patterns: Vec<Vec<u32>>,
+}
+
+impl Query {
+   /// Builds the query for literal `patterns`, or `None` when some pattern
+   /// is too short to narrow anything down.
+   pub fn literal<S: AsRef<str>>(patterns: &[S]) -> Option<Query> {
+      let mut trigrams = Vec::with_capacity(patterns.len());
+
+      for pattern in patterns {
+         let folded: String = casefold::fold_str(pattern.as_ref()).into_iter().collect();
+         let pattern_trigrams = trigrams_of(folded.as_bytes());
+
+         if pattern_trigrams.is_empty() {
+            return None;
+         }
+
+         trigrams.push(pattern_trigrams);
+      }
+
+      Some(Query { patterns: trigrams })
+   }
+}
+
+/// Returns the sorted, distinct trigrams of `bytes`.
+fn trigrams_of(bytes: &[u8]) -> Vec<u32> {
+   let mut trigrams: Vec<u32> = bytes.windows(3)
+      .map(|w| u32::from(w[0]) << 16 | u32::from(w[1]) << 8 | u32::from(w[2]))
+      .collect();
+
+   trigrams.sort_unstable();
+   trigrams.dedup();
+
+   trigrams
+}
+
+/// The distinct trigrams of a stream of bytes fed to it in pieces.
+#[derive(Default)]
+struct Trigrams {
+   seen: HashSet<u32>,
+   /// The last two bytes fed, once there are two.
+   last: u32,
+   fed: usize,
+}
+
+impl Trigrams {
+   fn push(&mut self, byte: u8) {
+      self.last = (self.last << 8 | u32::from(byte)) & 0xff_ffff;
+      self.fed += 1;
+
+      if self.fed >= 3 {
+         self.seen.insert(self.last);
+      }
+   }
+
+   fn push_folded(&mut self, text: &str) {
+      let mut utf8 = [0; 4];
+
+      for c in text.chars().flat_map(casefold::fold) {
+         for &byte in c.encode_utf8(&mut utf8).as_bytes() {
+            self.push(byte);
+         }
+      }
+   }
+
+   /// Feeds the text at the start of `bytes` case folded, and bytes that
+   /// are not UTF-8 as they are. Returns how many bytes were used: all of
+   /// them, unless `bytes` ends inside a character and more may follow.
+   fn feed(&mut self, mut bytes: &[u8], more: bool) -> usize {
+      let len = bytes.len();
+
+      loop {
+         match str::from_utf8(bytes) {
+            Ok(text) => {
+               self.push_folded(text);
+
+               return len;
+            },
+            Err(e) => {
+               let (text, rest) = bytes.split_at(e.valid_up_to());
+
+               self.push_folded(str::from_utf8(text).unwrap());
+
+               let invalid = match e.error_len() {
+                  Some(invalid) => invalid,
+                  None if more => return len - rest.len(),
+                  None => rest.len(),
+               };
+
+               for &byte in &rest[..invalid] {
+                  self.push(byte);
+               }
+
+               bytes = &rest[invalid..];
+            },
+         }
+      }
+   }
+
+   fn into_sorted(self) -> Vec<u32> {
+      let mut trigrams: Vec<u32> = self.seen.into_iter().collect();
+
+      trigrams.sort_unstable();
+
+      trigrams
+   }
+}
+
+/// Reads a file, decompressed if need be, and returns the trigrams of its
+/// case folded contents. Bytes that are not text are indexed as they are.
+/// The file is read a buffer at a time, so its size does not matter.
+///
+/// Compressed files are recognized by their magic bytes, whatever their
+/// name, just as searches read them.
+fn file_trigrams(path: &Path) -> io::Result<Vec<u32>> {
+   let (mut reader, _) = input::open(path, false)?;
+   let mut trigrams = Trigrams::default();
+   // The start of a character split across buffers.
+   let mut partial = Vec::new();
+
+   loop {
+      let buf = reader.fill_buf()?;
+
+      if buf.is_empty() {
+         break;
+      }
+
+      let read = buf.len();
+
+      partial.extend_from_slice(buf);
+      reader.consume(read);
+
+      let used = trigrams.feed(&partial, true);
+
+      partial.drain(..used);
+   }
+
+   trigrams.feed(&partial, false);
+
+   Ok(trigrams.into_sorted())
+}
+
+fn relative_path(dir: &Path, path: &Path) -> String {
+   let relative = path.strip_prefix(dir).unwrap_or(path);
+
+   relative.components()
+      .map(|c| c.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/")
+}
+
+fn modified(metadata: &Metadata) -> Option<(u64, u32)> {
+   let since_epoch = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+   Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+fn put_u64(bytes: &mut Vec<u8>, n: u64) {
+   bytes.extend_from_slice(&n.to_le_bytes());
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+   if bytes.len() < len {
+      return None;
+   }
+
+   let (taken, rest) = bytes.split_at(len);
+   *bytes = rest;
+
+   Some(taken)
+}
+
+fn take_u64(bytes: &mut &[u8]) -> Option<u64> {
+   let mut n = [0; 8];
+   n.copy_from_slice(take(bytes, 8)?);
+
+   Some(u64::from_le_bytes(n))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use std::fs::File;
+   use std::time::{Duration, SystemTime};
+
+   use tempfile::TempDir;
+
+   /// Creates a directory with a poem and a log to index.
+   fn corpus() -> TempDir {
+      let dir = TempDir::new().unwrap();
+      fs::create_dir_all(dir.path().join("logs")).unwrap();
+
+      fs::write(dir.path().join("poem.txt"), "I'm nobody! Who are you?\n").unwrap();
+      fs::write(dir.path().join("logs/app.log"), "GET /sleep HTTP/1.1\n").unwrap();
+
+      dir
+   }
+
+   fn candidates(index: &Index, dir: &Path, patterns: &[&str]) -> Vec<Option<bool>> {
+      let query = Query::literal(patterns).unwrap();
+
+      ["poem.txt", "logs/app.log"].iter()
+         .map(|file| index.may_match(dir, &dir.join(file), &query))
+         .collect()
+   }
+
+   #[test]
+   fn narrows_candidates() {
+      let corpus = corpus();
+      let dir = corpus.path();
+
+      assert_eq!(BuildStats { files: 2, updated: 2 }, Index::build(dir).unwrap());
+
+      let index = Index::load(dir).unwrap().unwrap();
+
+      assert_eq!(vec![Some(true), Some(false)], candidates(&index, dir, &["NOBODY"]));
+      assert_eq!(vec![Some(false), Some(true)], candidates(&index, dir, &["sleep"]));
+      assert_eq!(vec![Some(true), Some(true)], candidates(&index, dir, &["who", "http"]));
+      assert_eq!(vec![Some(false), Some(false)], candidates(&index, dir, &["somebody"]));
+      assert!(Query::literal(&["ab"]).is_none());
+
+   }
+
+   #[test]
+   fn indexes_compressed_contents() {
+      let corpus = corpus();
+      let dir = corpus.path();
+
+      // Rotated logs keep no compression extension.
+      let compressed = zstd::encode_all(&b"GET /sleep HTTP/1.1\n"[..], 0).unwrap();
+
+      fs::write(dir.join("logs/app.log"), compressed).unwrap();
+      Index::build(dir).unwrap();
+
+      let index = Index::load(dir).unwrap().unwrap();
+
+      assert_eq!(vec![Some(false), Some(true)], candidates(&index, dir, &["sleep"]));
+   }
+
+   #[test]
+   fn skips_unreadable_files() {
+      let corpus = corpus();
+      let dir = corpus.path();
+
+      fs::write(dir.join("logs/app.log"), [0x1f, 0x8b, 0x08, 0, 0xff]).unwrap();
+
+      assert_eq!(BuildStats { files: 1, updated: 1 }, Index::build(dir).unwrap());
+
+      let index = Index::load(dir).unwrap().unwrap();
+
+      assert_eq!(vec![Some(true), None], candidates(&index, dir, &["nobody"]));
+   }
+
+   #[test]
+   fn reads_across_buffers() {
+      let corpus = corpus();
+      let path = corpus.path().join("poem.txt");
+
+      // 'É' straddles the end of the first buffer of 8 KiB, and 0xff is no
+      // UTF-8 at all.
+      let mut contents = "x".repeat(8191).into_bytes();
+      contents.extend_from_slice("ÉCOLE \u{ff}".as_bytes());
+      contents.push(0xff);
+      contents.extend_from_slice(" ÉTÉ".as_bytes());
+      fs::write(&path, &contents).unwrap();
+
+      let mut expected = "x".repeat(8191).into_bytes();
+      expected.extend_from_slice("école \u{ff}".as_bytes());
+      expected.push(0xff);
+      expected.extend_from_slice(" été".as_bytes());
+
+      assert_eq!(trigrams_of(&expected), file_trigrams(&path).unwrap());
+   }
+
+   #[test]
+   fn updates_incrementally() {
+      let corpus = corpus();
+      let dir = corpus.path();
+
+      Index::build(dir).unwrap();
+
+      let file = File::options().append(true).open(dir.join("poem.txt")).unwrap();
+      file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+
+      let index = Index::load(dir).unwrap().unwrap();
+
+      assert_eq!(vec![None, Some(false)], candidates(&index, dir, &["nobody"]));
+      assert_eq!(BuildStats { files: 2, updated: 1 }, Index::build(dir).unwrap());
+
+      let index = Index::load(dir).unwrap().unwrap();
+
+      assert_eq!(vec![Some(true), Some(false)], candidates(&index, dir, &["nobody"]));
+
+   }
+}
@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use xz2::bufread::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+/// Compression formats that are decompressed while searching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+   Gzip,
+   Bzip2,
+   Xz,
+   Zstd,
+}
+
+impl Compression {
+   /// Identifies the format from the first bytes of a file. Every file is
+   /// checked, so the magic is matched as far as the formats define it.
+   pub fn from_magic(bytes: &[u8]) -> Option<Compression> {
+      if bytes.starts_with(&[0x1f, 0x8b, 0x08]) {
+         Some(Compression::Gzip)
+      } else if bytes.starts_with(b"BZh") && bytes.get(3).is_some_and(|level| (b'1'..=b'9').contains(level)) {
+         Some(Compression::Bzip2)
+      } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+         Some(Compression::Xz)
+      } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+         Some(Compression::Zstd)
+      } else {
+         None
+      }
+   }
+}
+
+/// Opens `path` for reading, returning the reader and the compression that
+/// is transparently undone by it, as detected by the file's magic bytes.
+///
+/// A file in no known format is read as it is, unless `force` is set: then
+/// it is an `InvalidData` error, as it is for `zcat`.
+pub fn open(path: &Path, force: bool) -> io::Result<(Box<dyn BufRead>, Option<Compression>)> {
+   let mut reader = BufReader::new(File::open(path)?);
+   let compression = Compression::from_magic(reader.fill_buf()?);
+
+   if force && compression.is_none() {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, "not in a known compressed format"));
+   }
+
+   let reader: Box<dyn BufRead> = match compression {
+      None => Box::new(reader),
+      Some(Compression::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+      Some(Compression::Bzip2) => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+      Some(Compression::Xz) => Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader))),
+      Some(Compression::Zstd) => Box::new(BufReader::new(ZstdDecoder::with_buffer(reader)?)),
+   };
+
+   Ok((reader, compression))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use std::fs;
+   use std::io::{Read, Write};
+
+   use std::path::PathBuf;
+
+   use tempfile::TempDir;
+
+   const TEXT: &str = "Rust:\nsafe, fast, productive.\nPick three.\n";
+
+   fn compress(compression: Compression) -> Vec<u8> {
+      match compression {
+         Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(TEXT.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+         },
+         Compression::Bzip2 => {
+            let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+            encoder.write_all(TEXT.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+         },
+         Compression::Xz => {
+            let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+            encoder.write_all(TEXT.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+         },
+         Compression::Zstd => zstd::encode_all(TEXT.as_bytes(), 0).unwrap(),
+      }
+   }
+
+   fn read(path: &Path, force: bool) -> (String, Option<Compression>) {
+      let (mut reader, compression) = open(path, force).unwrap();
+      let mut text = String::new();
+
+      reader.read_to_string(&mut text).unwrap();
+
+      (text, compression)
+   }
+
+   fn temp_file(dir: &TempDir, name: &str, contents: &[u8]) -> PathBuf {
+      let path = dir.path().join(name);
+      fs::write(&path, contents).unwrap();
+
+      path
+   }
+
+   #[test]
+   fn decompresses_by_magic_bytes() {
+      let formats = [
+         ("log.gz", Compression::Gzip),
+         ("log.bz2", Compression::Bzip2),
+         ("log.xz", Compression::Xz),
+         ("log.zst", Compression::Zstd),
+      ];
+
+      let dir = TempDir::new().unwrap();
+
+      for &(name, compression) in &formats {
+         let path = temp_file(&dir, name, &compress(compression));
+
+         assert_eq!((TEXT.to_string(), Some(compression)), read(&path, false));
+      }
+   }
+
+   #[test]
+   fn ignores_file_name() {
+      let dir = TempDir::new().unwrap();
+      let path = temp_file(&dir, "app.log.1", &compress(Compression::Zstd));
+
+      assert_eq!((TEXT.to_string(), Some(Compression::Zstd)), read(&path, false));
+      assert_eq!((TEXT.to_string(), Some(Compression::Zstd)), read(&path, true));
+   }
+
+   #[test]
+   fn plain_file_with_compressed_name() {
+      let dir = TempDir::new().unwrap();
+      let path = temp_file(&dir, "plain.gz", TEXT.as_bytes());
+
+      assert_eq!((TEXT.to_string(), None), read(&path, false));
+      assert_eq!(io::ErrorKind::InvalidData, open(&path, true).err().unwrap().kind());
+
+      let path = temp_file(&dir, "notes.txt", b"BZh is where bzip2 files start.\n");
+
+      assert_eq!(None, open(&path, false).unwrap().1);
+   }
+}
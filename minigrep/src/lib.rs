@@ -1,11 +1,70 @@
+extern crate bzip2;
+extern crate flate2;
+extern crate regex;
+extern crate regex_syntax;
+#[cfg(test)]
+extern crate tempfile;
+extern crate xz2;
+extern crate zstd;
+
 use std::env;
 use std::fs;
 use std::error::Error;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+mod aho_corasick;
+mod casefold;
+mod color;
+mod glob;
+mod index;
+mod input;
+pub mod fuzzy;
+mod matcher;
+mod replace;
+mod search;
+mod walk;
+
+use color::{ColorChoice, Colors};
+use fuzzy::{FuzzyMatch, FuzzyMatcher};
+use index::{Index, Query};
+use walk::Filter;
+
+pub use aho_corasick::PatternMatch;
+pub use matcher::{Boundary, Matcher};
+pub use search::{Match, Matches, Searcher};
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
    use super::*;
 
+   use std::io::Write;
+
+   use tempfile::TempDir;
+
+   fn matches(matcher: Matcher, contents: &str) -> Vec<(String, Vec<usize>)> {
+      let mut results = Vec::new();
+
+      Searcher::new(matcher).search_reader(contents.as_bytes(), |matches| {
+         results.push((matches[0].line.to_string(), matched_patterns(matches)));
+      }).unwrap();
+
+      results
+   }
+
+   fn lines(query: &str, contents: &str, case_sensitive: bool) -> Vec<String> {
+      matches(Matcher::literal(&[query], case_sensitive), contents).into_iter()
+         .map(|(line, _)| line)
+         .collect()
+   }
+
+   fn args(args: &[&str]) -> Config {
+      let args = args.iter().map(|arg| arg.to_string());
+
+      Config::new(Some("minigrep".to_string()).into_iter().chain(args)).unwrap()
+   }
+
    #[test]
    fn case_sensitive() {
       let query = "duct";
@@ -17,7 +76,7 @@ Duct tape.";
 
       assert_eq!(
          vec!["safe, fast, productive."],
-         search(query, contents)
+         lines(query, contents, true)
       )
    }
 
@@ -32,63 +91,709 @@ Trust me.";
 
       assert_eq!(
          vec!["Rust:", "Trust me."],
-         search_case_insensitive(query, contents)
+         lines(query, contents, false)
+      );
+   }
+
+   #[test]
+   fn case_insensitive_unicode() {
+      let query = "STRASSE";
+      let contents = "\
+Hauptstraße 1
+Bahnhofstrasse 2
+Marktplatz 3";
+
+      assert_eq!(
+         vec!["Hauptstraße 1", "Bahnhofstrasse 2"],
+         lines(query, contents, false)
       );
    }
+
+   #[test]
+   fn multiple_patterns() {
+      let matcher = Matcher::literal(&["tape", "fast", "safe"], true);
+      let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+      assert_eq!(
+         vec![
+            ("safe, fast, productive.".to_string(), vec![2, 1]),
+            ("Duct tape.".to_string(), vec![0]),
+         ],
+         matches(matcher, contents)
+      );
+   }
+
+   #[test]
+   fn pattern_options() {
+      let config = args(&["-e", "one", "-f", "patterns.txt", "-e", "two", "poem.txt"]);
+
+      assert_eq!(vec!["one", "two"], config.patterns);
+      assert_eq!(vec!["patterns.txt"], config.pattern_files);
+      assert_eq!(vec!["poem.txt"], config.filenames);
+
+      let config = args(&["one", "poem.txt", "app.log.gz", "-z"]);
+
+      assert_eq!(vec!["one"], config.patterns);
+      assert_eq!(vec!["poem.txt", "app.log.gz"], config.filenames);
+      assert!(config.decompress);
+      assert_eq!(ColorChoice::Auto, config.color);
+
+      let config = args(&["--color=never", "-n", "one", "poem.txt"]);
+
+      assert_eq!(ColorChoice::Never, config.color);
+      assert!(config.line_numbers);
+   }
+
+   #[test]
+   fn replace_options() {
+      let config = args(&["-E", "(\\w+)@", "--replace", "$1 at", "--in-place", "--backup=.orig", "mail.txt"]);
+
+      assert!(config.regex);
+      assert_eq!(Some("$1 at".to_string()), config.replace);
+      assert!(config.in_place);
+      assert_eq!(Some(".orig".to_string()), config.backup);
+
+      // -i and -r are ignore-case and recursive to grep users; no short
+      // flag rewrites files.
+      let config = args(&["--replace", "x", "-i", "query", "poem.txt"]);
+
+      assert!(!config.in_place);
+
+      let args = vec!["minigrep", "--in-place", "query", "poem.txt"];
+
+      assert!(Config::new(args.into_iter().map(String::from)).is_err());
+   }
+
+   #[test]
+   fn in_place_skips_walked_compressed_files() {
+      let dir = TempDir::new().unwrap();
+      let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+
+      encoder.write_all(b"old\n").unwrap();
+
+      let compressed = encoder.finish().unwrap();
+
+      fs::write(dir.path().join("a.txt"), "old\n").unwrap();
+      fs::write(dir.path().join("b.log.gz"), &compressed).unwrap();
+      fs::write(dir.path().join("c.txt"), "old\n").unwrap();
+
+      let root = dir.path().to_string_lossy();
+
+      run(args(&["--replace", "new", "--in-place", "old", &root])).unwrap();
+
+      assert_eq!("new\n", fs::read_to_string(dir.path().join("a.txt")).unwrap());
+      assert_eq!(compressed, fs::read(dir.path().join("b.log.gz")).unwrap());
+      assert_eq!("new\n", fs::read_to_string(dir.path().join("c.txt")).unwrap());
+
+      let named = dir.path().join("b.log.gz").to_string_lossy().into_owned();
+
+      assert!(run(args(&["--replace", "new", "--in-place", "old", &named])).is_err());
+   }
+
+   #[test]
+   fn decompress_searches_walked_plain_files() {
+      let dir = TempDir::new().unwrap();
+
+      fs::write(dir.path().join("a.txt"), "old\n").unwrap();
+
+      let root = dir.path().to_string_lossy();
+
+      run(args(&["-z", "--replace", "new", "--in-place", "old", &root])).unwrap();
+
+      assert_eq!("new\n", fs::read_to_string(dir.path().join("a.txt")).unwrap());
+
+      let named = dir.path().join("a.txt").to_string_lossy().into_owned();
+
+      assert!(run(args(&["-z", "old", &named])).is_err());
+   }
+
+   #[test]
+   fn matching_modes() {
+      let config = args(&["-E", "-F", "-w", "a.b", "poem.txt"]);
+
+      assert!(config.fixed_strings);
+      assert_eq!(Boundary::Word, config.boundary);
+      assert!(!config.multiline);
+
+      let config = args(&["-x", "-U", "a.b", "poem.txt"]);
+
+      assert_eq!(Boundary::Line, config.boundary);
+      assert!(config.multiline);
+   }
+
+   #[test]
+   fn fuzzy_options() {
+      let config = args(&["--fuzzy", "--top", "5", "tp", "poem.txt"]);
+
+      assert!(config.fuzzy);
+      assert_eq!(Some(5), config.top);
+
+      let args = vec!["minigrep", "--top", "5", "tp", "poem.txt"];
+
+      assert!(Config::new(args.into_iter().map(String::from)).is_err());
+   }
+
+   #[test]
+   fn filter_options() {
+      let config = args(&["-t", "rust", "-T", "cargo", "-g", "!target/**", "fn", "."]);
+
+      assert_eq!(vec!["rust"], config.types);
+      assert_eq!(vec!["cargo"], config.types_not);
+      assert_eq!(vec!["!target/**"], config.globs);
+
+      let args = vec!["minigrep", "--type", "cobol", "fn", "."];
+
+      assert!(Config::new(args.into_iter().map(String::from)).is_err());
+   }
+
+   #[test]
+   fn index_options() {
+      let config = args(&["index", "build", "logs", "archive"]);
+
+      assert!(config.build_index);
+      assert_eq!(vec!["logs", "archive"], config.filenames);
+
+      let config = args(&["--index", "index", "build"]);
+
+      assert!(!config.build_index);
+      assert!(config.use_index);
+      assert_eq!(vec!["index"], config.patterns);
+      assert_eq!(vec!["build"], config.filenames);
+   }
 }
 
 pub fn run(config: Config) -> Result<(), Box<dyn Error>>{
-   let contents = fs::read_to_string(config.filename)?;
+   if config.build_index {
+      for dir in &config.filenames {
+         let stats = Index::build(Path::new(dir))?;
+
+         println!("Indexed {} files in {} ({} read).", stats.files, dir, stats.updated);
+      }
+
+      return Ok(());
+   }
 
-   let results = if config.case_sensitive {
-      search(&config.query, &contents)
+   let patterns = config.load_patterns()?;
+   let colors = Colors::new(config.color);
+
+   // Regular expressions are not narrowed down by the index.
+   let query = if config.use_index && (!config.regex || config.fixed_strings) && !config.fuzzy {
+      Query::literal(&patterns)
    } else {
-      search_case_insensitive(&config.query, &contents)
+      None
    };
 
-   for line in results {
-      println!("{}", line);
+   let files = config.files(query.as_ref())?;
+
+   if config.fuzzy {
+      if patterns.len() != 1 {
+         return Err("Option --fuzzy takes a single pattern.".into());
+      }
+
+      return run_fuzzy(&config, &patterns[0], &files, &colors);
+   }
+
+   let matcher = if config.regex && !config.fixed_strings {
+      Matcher::regex(&patterns, config.case_sensitive)?
+   } else {
+      Matcher::literal(&patterns, config.case_sensitive)
+   };
+
+   let searcher = Searcher::new(matcher.boundary(config.boundary))
+      .multiline(config.multiline);
+   let matcher = searcher.matcher();
+
+   for file in &files.files {
+      let filename = file.path.to_string_lossy();
+      let (reader, compression) = match input::open(&file.path, config.decompress && !file.walked) {
+         Err(ref e) if file.walked => {
+            walk::skip(&file.path, e);
+            continue;
+         },
+         opened => opened?,
+      };
+
+      if let Some(ref replacement) = config.replace {
+         if config.dry_run || config.in_place {
+            // Only files named on the command line stop the edit halfway;
+            // files found in a directory are left as they are.
+            if compression.is_some() {
+               if file.walked {
+                  continue;
+               }
+
+               return Err(format!("{}: cannot edit a compressed file", filename).into());
+            }
+
+            let contents = match fs::read_to_string(&file.path) {
+               Err(ref e) if file.walked && e.kind() == io::ErrorKind::InvalidData => continue,
+               Err(ref e) if file.walked => {
+                  walk::skip(&file.path, e);
+                  continue;
+               },
+               contents => contents?,
+            };
+
+            let (replaced, changes) = replace::replace_all(matcher, &contents, replacement);
+
+            if config.dry_run {
+               replace::print_diff(&filename, &changes);
+            } else if !changes.is_empty() {
+               match replace::write_atomic(&file.path, replaced.as_bytes(), config.backup.as_deref()) {
+                  Err(ref e) if file.walked => walk::skip(&file.path, e),
+                  written => written?,
+               }
+            }
+
+            continue;
+         }
+      }
+
+      let result = searcher.search_reader(reader, |matches| {
+         // A multiline match may take the terminator of its last line along.
+         let line = matches[0].line;
+         let line = line.strip_suffix('\n').map_or(line, |line| line.strip_suffix('\r').unwrap_or(line));
+
+         let shown = if files.show_filenames { Some(&*filename) } else { None };
+         let mut output = prefix(&config, &colors, shown, matches[0].line_number);
+
+         if patterns.len() > 1 {
+            let names: Vec<&str> = matched_patterns(matches).iter()
+               .map(|&id| patterns[id].as_str())
+               .collect();
+
+            output.push_str(&format!("[{}] ", names.join(", ")));
+         }
+
+         match config.replace {
+            Some(ref replacement) => {
+               let (line, spans) = matcher.replace_spans(line, replacement);
+
+               output.push_str(&colors.highlight(&line, spans));
+            },
+            None => {
+               let spans = matches.iter()
+                  .map(|m| m.byte_range.start.min(line.len())..m.byte_range.end.min(line.len()));
+
+               output.push_str(&colors.highlight(line, spans));
+            },
+         }
+
+         println!("{}", output);
+      });
+
+      match result {
+         // Files found in a directory that are not text are skipped.
+         Err(ref e) if file.walked && e.kind() == io::ErrorKind::InvalidData => {},
+         result => result?,
+      }
    }
 
    Ok(())
 }
 
-fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-   contents.lines()
-      .filter(|line| line.contains(query))
-      .collect()
+/// The files to search.
+struct Files {
+   files: Vec<File>,
+   /// Whether output lines say which file they are from.
+   show_filenames: bool,
 }
 
-fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-   let query = query.to_lowercase();
+struct File {
+   path: PathBuf,
+   /// Whether the file was found by walking a directory, rather than named
+   /// on the command line.
+   walked: bool,
+}
+
+/// Drops the files of `dir` that its index rules out for `query`.
+fn narrow(dir: &Path, files: Vec<PathBuf>, query: &Query) -> io::Result<Vec<PathBuf>> {
+   let index = match Index::load(dir)? {
+      Some(index) => index,
+      None => {
+         eprintln!("minigrep: {} has no index, searching every file.", dir.display());
+
+         return Ok(files);
+      },
+   };
+
+   let mut stale = 0;
+
+   let files: Vec<PathBuf> = files.into_iter()
+      .filter(|file| match index.may_match(dir, file, query) {
+         Some(may_match) => may_match,
+         None => {
+            stale += 1;
+            true
+         },
+      })
+      .collect();
 
-   contents.lines()
-      .filter(|line| line.to_lowercase().contains(&query))
-      .collect()
+   if stale > 0 {
+      eprintln!(
+         "minigrep: index of {} is out of date for {} files, run `minigrep index build {}`.",
+         dir.display(), stale, dir.display()
+      );
+   }
+
+   Ok(files)
 }
 
+/// A line of one of the searched files, for ranking.
+struct FileLine {
+   file: usize,
+   line_number: usize,
+   line: String,
+}
+
+/// Ranks the lines of every file against a fuzzy `query`, printing the best
+/// first.
+fn run_fuzzy(config: &Config, query: &str, files: &Files, colors: &Colors) -> Result<(), Box<dyn Error>> {
+   let matcher = FuzzyMatcher::new(query, config.case_sensitive);
+   let mut results: Vec<(FuzzyMatch, FileLine)> = Vec::new();
+
+   for (index, file) in files.files.iter().enumerate() {
+      let (reader, _) = match input::open(&file.path, config.decompress && !file.walked) {
+         Err(ref e) if file.walked => {
+            walk::skip(&file.path, e);
+            continue;
+         },
+         opened => opened?,
+      };
+
+      for (line_index, line) in reader.lines().enumerate() {
+         let line = match line {
+            Err(ref e) if file.walked && e.kind() == io::ErrorKind::InvalidData => break,
+            line => line?,
+         };
+
+         if let Some(m) = matcher.score(&line) {
+            results.push((m, FileLine { file: index, line_number: line_index + 1, line }));
+         }
+      }
+   }
+
+   fuzzy::rank(&mut results, |result| result.line.len());
+
+   if let Some(top) = config.top {
+      results.truncate(top);
+   }
+
+   for (m, result) in results {
+      let line = &result.line;
+      let spans = m.positions.iter()
+         .map(|&pos| pos..pos + line[pos..].chars().next().map_or(0, char::len_utf8));
+
+      let filename = files.files[result.file].path.to_string_lossy();
+      let shown = if files.show_filenames { Some(&*filename) } else { None };
+
+      let mut output = prefix(config, colors, shown, result.line_number);
+      output.push_str(&colors.highlight(line, spans));
+
+      println!("{}", output);
+   }
+
+   Ok(())
+}
+
+/// Returns the file name and line number to show before a line, as far as
+/// they are wanted.
+fn prefix(config: &Config, colors: &Colors, filename: Option<&str>, line_number: usize) -> String {
+   let mut prefix = String::new();
+
+   if let Some(filename) = filename {
+      prefix.push_str(&colors.filename(filename));
+      prefix.push(':');
+   }
+
+   if config.line_numbers {
+      prefix.push_str(&colors.line_number(line_number));
+      prefix.push(':');
+   }
+
+   prefix
+}
+
+/// Returns the patterns of `matches` in order of first occurrence.
+fn matched_patterns(matches: &[Match]) -> Vec<usize> {
+   let mut patterns = Vec::new();
+
+   for m in matches {
+      if !patterns.contains(&m.pattern) {
+         patterns.push(m.pattern);
+      }
+   }
+
+   patterns
+}
+
+/// What `minigrep` takes, shown when its arguments are wrong.
+pub const USAGE: &str = "\
+Usage: minigrep [OPTION]... PATTERN FILE...
+       minigrep [OPTION]... -e PATTERN... FILE...
+       minigrep index build DIR...
+
+Searches each FILE, or every file below it if it is a directory, for lines
+matching PATTERN. Files compressed with gzip, bzip2, xz or zstd are recognized
+by their first bytes and searched decompressed.
+
+  -e PATTERN             search for PATTERN; may be given more than once
+  -f FILE                search for the patterns in FILE, one per line
+  -E, --regex            read patterns as regular expressions
+  -F, --fixed-strings    read patterns as literal strings, even with -E
+  -w, --word-regexp      match whole words only
+  -x, --line-regexp      match whole lines only
+  -U, --multiline        let matches span lines
+      --replace TEXT     show each match replaced by TEXT
+      --dry-run          with --replace, show the edits as a diff
+      --in-place         with --replace, edit the files
+      --backup[=SUFFIX]  with --in-place, keep each original with SUFFIX (.bak)
+  -z, --decompress       fail on a named FILE that is not compressed; files
+                         found in a directory are searched as plain text when
+                         they are not
+  -n, --line-number      show the line number of each line
+      --color=WHEN       highlight matches: auto, always or never
+  -g, --glob GLOB        search only files matching GLOB; !GLOB leaves them out
+  -t, --type TYPE        search only files of TYPE
+  -T, --type-not TYPE    leave out files of TYPE
+      --fuzzy            rank lines by how well they fuzzy match PATTERN
+      --top N            with --fuzzy, show only the N best lines
+      --index            leave out the files a directory's index rules out
+
+Set CASE_INSENSITIVE to ignore case.";
+
 pub struct Config {
-   pub query: String,
-   pub filename: String,
+   pub patterns: Vec<String>,
+   pub pattern_files: Vec<String>,
+   pub filenames: Vec<String>,
    pub case_sensitive: bool,
+   pub regex: bool,
+   pub fixed_strings: bool,
+   pub boundary: Boundary,
+   pub multiline: bool,
+   pub replace: Option<String>,
+   pub dry_run: bool,
+   pub in_place: bool,
+   pub backup: Option<String>,
+   /// Whether a named file that is not compressed is an error. Files found
+   /// in a directory are searched either way.
+   pub decompress: bool,
+   pub line_numbers: bool,
+   pub color: ColorChoice,
+   pub fuzzy: bool,
+   pub top: Option<usize>,
+   pub globs: Vec<String>,
+   pub types: Vec<String>,
+   pub types_not: Vec<String>,
+   pub use_index: bool,
+   pub build_index: bool,
 }
 
 impl Config {
-   pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
+   pub fn new<I: Iterator<Item = String>>(mut args: I) -> Result<Config, &'static str> {
       args.next();
 
-      let query = match args.next() {
-         Some(arg) => arg,
-         None => return Err("Did not get a query string."),
-      };
+      let mut patterns = Vec::new();
+      let mut pattern_files = Vec::new();
+      let mut regex = false;
+      let mut fixed_strings = false;
+      let mut boundary = Boundary::None;
+      let mut multiline = false;
+      let mut replace = None;
+      let mut dry_run = false;
+      let mut in_place = false;
+      let mut backup = None;
+      let mut decompress = false;
+      let mut line_numbers = false;
+      let mut color = ColorChoice::Auto;
+      let mut fuzzy = false;
+      let mut top = None;
+      let mut globs = Vec::new();
+      let mut types = Vec::new();
+      let mut types_not = Vec::new();
+      let mut use_index = false;
+      let mut operands = Vec::new();
 
-      let filename = match args.next() {
-         Some(arg) => arg,
-         None => return Err("Did not get a file name."),
-      };
+      while let Some(arg) = args.next() {
+         match arg.as_str() {
+            "-e" => match args.next() {
+               Some(pattern) => patterns.push(pattern),
+               None => return Err("Option -e requires a pattern."),
+            },
+            "-f" => match args.next() {
+               Some(file) => pattern_files.push(file),
+               None => return Err("Option -f requires a file name."),
+            },
+            "-E" | "--regex" => regex = true,
+            "-F" | "--fixed-strings" => fixed_strings = true,
+            "-w" | "--word-regexp" => boundary = Boundary::Word,
+            "-x" | "--line-regexp" => boundary = Boundary::Line,
+            "-U" | "--multiline" => multiline = true,
+            "--replace" => match args.next() {
+               Some(replacement) => replace = Some(replacement),
+               None => return Err("Option --replace requires a replacement."),
+            },
+            "--dry-run" => dry_run = true,
+            "--in-place" => in_place = true,
+            "--backup" => backup = Some(String::from(".bak")),
+            "-z" | "--decompress" => decompress = true,
+            "-n" | "--line-number" => line_numbers = true,
+            "-g" | "--glob" => match args.next() {
+               Some(glob) => globs.push(glob),
+               None => return Err("Option --glob requires a glob."),
+            },
+            "-t" | "--type" | "-T" | "--type-not" => {
+               let name = match args.next() {
+                  Some(name) => name,
+                  None => return Err("Options --type and --type-not require a file type."),
+               };
+
+               if walk::file_type(&name).is_none() {
+                  return Err("Unknown file type.");
+               }
+
+               if arg == "-t" || arg == "--type" {
+                  types.push(name);
+               } else {
+                  types_not.push(name);
+               }
+            },
+            "--fuzzy" => fuzzy = true,
+            "--index" => use_index = true,
+            "--top" => match args.next().and_then(|n| n.parse().ok()) {
+               Some(n) => top = Some(n),
+               None => return Err("Option --top requires a number."),
+            },
+            _ if arg.starts_with("--color=") => match ColorChoice::parse(&arg["--color=".len()..]) {
+               Some(choice) => color = choice,
+               None => return Err("Option --color must be auto, always or never."),
+            },
+            _ if arg.starts_with("--backup=") => backup = Some(arg["--backup=".len()..].to_string()),
+            _ => operands.push(arg),
+         }
+      }
+
+      if (dry_run || in_place) && replace.is_none() {
+         return Err("Options --dry-run and --in-place require --replace.");
+      }
+
+      if fuzzy && (multiline || replace.is_some()) {
+         return Err("Option --fuzzy cannot be combined with --multiline or --replace.");
+      }
+
+      if top.is_some() && !fuzzy {
+         return Err("Option --top requires --fuzzy.");
+      }
+
+      if multiline && replace.is_some() {
+         return Err("Option --multiline cannot be combined with --replace.");
+      }
+
+      if backup.is_some() && !in_place {
+         return Err("Option --backup requires --in-place.");
+      }
+
+      // `minigrep index build DIR...` indexes instead of searching.
+      let build_index = patterns.is_empty() && pattern_files.is_empty()
+         && operands.len() > 2 && operands[0] == "index" && operands[1] == "build";
+
+      let mut operands = operands.into_iter();
+
+      if build_index {
+         operands.nth(1);
+      }
+
+      if patterns.is_empty() && pattern_files.is_empty() && !build_index {
+         match operands.next() {
+            Some(arg) => patterns.push(arg),
+            None => return Err("Did not get a query string."),
+         }
+      }
+
+      let filenames: Vec<String> = operands.collect();
+
+      if filenames.is_empty() {
+         return Err("Did not get a file name.");
+      }
 
       let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
 
-      Ok(Config { query, filename, case_sensitive })
+      Ok(Config {
+         patterns,
+         pattern_files,
+         filenames,
+         case_sensitive,
+         regex,
+         fixed_strings,
+         boundary,
+         multiline,
+         replace,
+         dry_run,
+         in_place,
+         backup,
+         decompress,
+         line_numbers,
+         color,
+         fuzzy,
+         top,
+         globs,
+         types,
+         types_not,
+         use_index,
+         build_index,
+      })
+   }
+
+   /// Returns the files to search: the named files, and the files passing
+   /// the `--glob` and `--type` filters below the named directories.
+   ///
+   /// With `--index`, files that the directory's index rules out for `query`
+   /// are left out. Files changed since the index was built are kept.
+   fn files(&self, query: Option<&Query>) -> Result<Files, Box<dyn Error>> {
+      let filter = Filter::new(&self.globs, &self.types, &self.types_not)?;
+      let mut files = Vec::new();
+      let mut walked_any = false;
+
+      for filename in &self.filenames {
+         let path = Path::new(filename);
+
+         if path.is_dir() {
+            let mut found = Vec::new();
+            walk::walk(path, &filter, &mut found)?;
+
+            if let Some(query) = query {
+               found = narrow(path, found, query)?;
+            }
+
+            files.extend(found.into_iter().map(|path| File { path, walked: true }));
+            walked_any = true;
+         } else {
+            files.push(File { path: path.to_path_buf(), walked: false });
+         }
+      }
+
+      let show_filenames = walked_any || files.len() > 1;
+
+      Ok(Files { files, show_filenames })
+   }
+
+   /// Returns the `-e` patterns followed by the patterns of every `-f` file,
+   /// one per non-empty line.
+   pub fn load_patterns(&self) -> Result<Vec<String>, Box<dyn Error>> {
+      let mut patterns = self.patterns.clone();
+
+      for file in &self.pattern_files {
+         let contents = fs::read_to_string(file)?;
+
+         patterns.extend(contents.lines()
+            .filter(|line| !line.is_empty())
+            .map(String::from));
+      }
+
+      Ok(patterns)
    }
 }
\ No newline at end of file
@@ -8,6 +8,7 @@ use minigrep::Config;
 fn main() {
    let config = Config::new(env::args()).unwrap_or_else(|err| {
       eprintln!("Problem passing arguments: {}", err);
+      eprintln!("{}", minigrep::USAGE);
       process::exit(1);
    });
 
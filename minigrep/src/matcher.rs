@@ -0,0 +1,258 @@
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+use regex_syntax::is_word_character;
+
+use aho_corasick::{self, AhoCorasick, PatternMatch};
+
+/// Where a match has to start and end to count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+   /// Anywhere.
+   None,
+   /// On Unicode word boundaries: neither preceded nor followed by a word
+   /// character.
+   Word,
+   /// On line boundaries, covering whole lines.
+   Line,
+}
+
+/// Finds the patterns of a search in a line, or in a whole text when
+/// patterns span lines.
+pub struct Matcher {
+   kind: Kind,
+   boundary: Boundary,
+   case_sensitive: bool,
+}
+
+enum Kind {
+   /// Patterns matched literally, all at once.
+   Literal(AhoCorasick),
+   /// Patterns compiled as regular expressions, one per pattern.
+   Regex(Vec<Regex>),
+}
+
+impl Matcher {
+   /// Matches `patterns` as literal strings. Without `case_sensitive` they
+   /// are compared by their Unicode case folding.
+   pub fn literal<S: AsRef<str>>(patterns: &[S], case_sensitive: bool) -> Matcher {
+      Matcher {
+         kind: Kind::Literal(AhoCorasick::new(patterns, !case_sensitive)),
+         boundary: Boundary::None,
+         case_sensitive,
+      }
+   }
+
+   /// Matches `patterns` as regular expressions, failing on the first one
+   /// that does not compile. `^` and `$` match at the start and end of every
+   /// line.
+   pub fn regex<S: AsRef<str>>(patterns: &[S], case_sensitive: bool) -> Result<Matcher, regex::Error> {
+      let mut regexes = Vec::with_capacity(patterns.len());
+
+      for pattern in patterns {
+         regexes.push(compile(pattern.as_ref(), case_sensitive)?);
+      }
+
+      Ok(Matcher {
+         kind: Kind::Regex(regexes),
+         boundary: Boundary::None,
+         case_sensitive,
+      })
+   }
+
+   /// Only reports matches sitting on `boundary`.
+   pub fn boundary(mut self, boundary: Boundary) -> Matcher {
+      // Building the boundary into the regex keeps it from settling for a
+      // shorter match that would then be filtered out. The half boundaries
+      // only look outward, so patterns may start or end with non-word
+      // characters.
+      let wrap = match boundary {
+         Boundary::None => None,
+         Boundary::Word => Some((r"\b{start-half}(?:", r")\b{end-half}")),
+         Boundary::Line => Some(("^(?:", ")$")),
+      };
+
+      if let (Some((before, after)), &mut Kind::Regex(ref mut regexes)) = (wrap, &mut self.kind) {
+         for regex in regexes.iter_mut() {
+            *regex = compile(&format!("{}{}{}", before, regex.as_str(), after), self.case_sensitive)
+               .expect("anchoring a valid regex keeps it valid");
+         }
+      }
+
+      self.boundary = boundary;
+
+      self
+   }
+
+   /// Returns the non-overlapping matches in `text`, preferring the leftmost
+   /// and then the longest match.
+   pub fn find_all(&self, text: &str) -> Vec<PatternMatch> {
+      let mut all = Vec::new();
+
+      match self.kind {
+         Kind::Literal(ref ac) => {
+            if self.boundary == Boundary::None {
+               return ac.find_all(text);
+            }
+
+            ac.for_each_match(text, |m| all.push(m));
+         },
+         Kind::Regex(ref regexes) => {
+            for (pattern, regex) in regexes.iter().enumerate() {
+               all.extend(regex.find_iter(text).map(|m| PatternMatch {
+                  pattern,
+                  start: m.start(),
+                  end: m.end(),
+               }));
+            }
+         },
+      }
+
+      all.retain(|m| self.on_boundary(text, m));
+
+      aho_corasick::leftmost_longest(all)
+   }
+
+   fn on_boundary(&self, text: &str, m: &PatternMatch) -> bool {
+      let before = &text[..m.start];
+      let after = &text[m.end..];
+
+      match self.boundary {
+         Boundary::None => true,
+         Boundary::Word => {
+            m.start < m.end
+               && !before.chars().next_back().is_some_and(is_word_character)
+               && !after.chars().next().is_some_and(is_word_character)
+         },
+         Boundary::Line => {
+            (before.is_empty() || before.ends_with('\n'))
+               && (after.is_empty() || after.starts_with('\n') || after.starts_with("\r\n"))
+         },
+      }
+   }
+
+   /// Returns `line` with every match replaced by `replacement`. In regex
+   /// mode `replacement` may refer to capture groups as `$1` or `${name}`.
+   pub fn replace(&self, line: &str, replacement: &str) -> String {
+      self.replace_spans(line, replacement).0
+   }
+
+   /// Like `replace`, also returning where the replacements ended up in the
+   /// new line.
+   pub fn replace_spans(&self, line: &str, replacement: &str) -> (String, Vec<Range<usize>>) {
+      let mut replaced = String::with_capacity(line.len());
+      let mut spans = Vec::new();
+      let mut last = 0;
+
+      for m in self.find_all(line) {
+         replaced.push_str(&line[last..m.start]);
+
+         let start = replaced.len();
+
+         match self.kind {
+            Kind::Literal(_) => replaced.push_str(replacement),
+            Kind::Regex(ref regexes) => {
+               if let Some(caps) = regexes[m.pattern].captures_at(line, m.start) {
+                  caps.expand(replacement, &mut replaced);
+               }
+            },
+         }
+
+         spans.push(start..replaced.len());
+         last = m.end;
+      }
+
+      replaced.push_str(&line[last..]);
+
+      (replaced, spans)
+   }
+}
+
+fn compile(pattern: &str, case_sensitive: bool) -> Result<Regex, regex::Error> {
+   RegexBuilder::new(pattern)
+      .case_insensitive(!case_sensitive)
+      .multi_line(true)
+      .crlf(true)
+      .build()
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn regex_matches() {
+      let matcher = Matcher::regex(&["fa.t", r"\bp\w+"], true).unwrap();
+
+      let found: Vec<(usize, usize, usize)> = matcher.find_all("safe, fast, productive.")
+         .iter()
+         .map(|m| (m.pattern, m.start, m.end))
+         .collect();
+
+      assert_eq!(vec![(0, 6, 10), (1, 12, 22)], found);
+   }
+
+   #[test]
+   fn replace_literal() {
+      let matcher = Matcher::literal(&["rust"], false);
+
+      assert_eq!("crab: tcrab me.", matcher.replace("Rust: trust me.", "crab"));
+      assert_eq!("no match", matcher.replace("no match", "crab"));
+      assert_eq!(
+         ("crab: tcrab me.".to_string(), vec![0..4, 7..11]),
+         matcher.replace_spans("Rust: trust me.", "crab")
+      );
+   }
+
+   #[test]
+   fn replace_with_captures() {
+      let matcher = Matcher::regex(&[r"(\w+)@(\w+)\.com"], true).unwrap();
+
+      assert_eq!("mail bob at example", matcher.replace("mail bob@example.com", "$1 at $2"));
+
+      let matcher = Matcher::regex(&[r"(?P<year>\d{4})-(?P<month>\d{2})"], true).unwrap();
+
+      assert_eq!(
+         "due 03/2024 and 11/2025",
+         matcher.replace("due 2024-03 and 2025-11", "${month}/${year}")
+      );
+   }
+
+   fn spans(matcher: &Matcher, text: &str) -> Vec<(usize, usize)> {
+      matcher.find_all(text).iter().map(|m| (m.start, m.end)).collect()
+   }
+
+   #[test]
+   fn whole_words() {
+      let text = "naïve naïveté, über-naïve";
+      let matcher = Matcher::literal(&["naïve"], false).boundary(Boundary::Word);
+
+      assert_eq!(vec![(0, 6), (24, 30)], spans(&matcher, text));
+
+      let matcher = Matcher::regex(&["na.ve"], true).unwrap().boundary(Boundary::Word);
+
+      assert_eq!(vec![(0, 6), (24, 30)], spans(&matcher, text));
+
+      // The shorter alternative is no word here; the longer one is.
+      let matcher = Matcher::regex(&["ab|abc"], true).unwrap().boundary(Boundary::Word);
+
+      assert_eq!(vec![(0, 3)], spans(&matcher, "abc"));
+
+      let matcher = Matcher::regex(&["-x"], true).unwrap().boundary(Boundary::Word);
+
+      assert_eq!(vec![(2, 4)], spans(&matcher, "a -x a-x -xy"));
+   }
+
+   #[test]
+   fn whole_lines() {
+      let text = "ab\nab ab\r\nab";
+
+      let matcher = Matcher::literal(&["ab"], true).boundary(Boundary::Line);
+
+      assert_eq!(vec![(0, 2), (10, 12)], spans(&matcher, text));
+
+      let matcher = Matcher::regex(&["a|ab"], true).unwrap().boundary(Boundary::Line);
+
+      assert_eq!(vec![(0, 2), (10, 12)], spans(&matcher, text));
+   }
+}
@@ -0,0 +1,146 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use matcher::Matcher;
+
+/// A line changed by a replacement.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+   pub line_number: usize,
+   pub old: String,
+   pub new: String,
+}
+
+/// Replaces every match in `contents`, keeping the line terminators as they
+/// were. Returns the new contents along with the lines that changed.
+pub fn replace_all(matcher: &Matcher, contents: &str, replacement: &str) -> (String, Vec<Change>) {
+   let mut replaced = String::with_capacity(contents.len());
+   let mut changes = Vec::new();
+
+   for (index, line) in contents.split_inclusive('\n').enumerate() {
+      let body = line.trim_end_matches('\n').trim_end_matches('\r');
+      let new = matcher.replace(body, replacement);
+
+      replaced.push_str(&new);
+      replaced.push_str(&line[body.len()..]);
+
+      if new != body {
+         changes.push(Change {
+            line_number: index + 1,
+            old: body.to_string(),
+            new,
+         });
+      }
+   }
+
+   (replaced, changes)
+}
+
+/// Prints `changes` to `filename` as a unified diff.
+pub fn print_diff(filename: &str, changes: &[Change]) {
+   if changes.is_empty() {
+      return;
+   }
+
+   println!("--- {}", filename);
+   println!("+++ {}", filename);
+
+   // Replacements containing newlines add lines, shifting the new side.
+   let mut added = 0;
+
+   for change in changes {
+      let new_lines: Vec<&str> = change.new.split('\n').collect();
+
+      println!("@@ -{},1 +{},{} @@", change.line_number, change.line_number + added, new_lines.len());
+      println!("-{}", change.old);
+
+      for line in &new_lines {
+         println!("+{}", line);
+      }
+
+      added += new_lines.len() - 1;
+   }
+}
+
+/// Replaces the contents of `path` atomically: the new contents are written
+/// to a temporary file next to it, which is then renamed over the original.
+/// With a `backup` suffix the original is first copied to `path` + suffix.
+pub fn write_atomic(path: &Path, contents: &[u8], backup: Option<&str>) -> io::Result<()> {
+   let tmp = temp_path(path)?;
+
+   let result = write_temp(path, &tmp, contents).and_then(|_| {
+      if let Some(suffix) = backup {
+         let mut backup_path = path.as_os_str().to_owned();
+         backup_path.push(suffix);
+
+         fs::copy(path, backup_path)?;
+      }
+
+      fs::rename(&tmp, path)
+   });
+
+   if result.is_err() {
+      let _ = fs::remove_file(&tmp);
+   }
+
+   result
+}
+
+fn temp_path(path: &Path) -> io::Result<PathBuf> {
+   let name = match path.file_name() {
+      Some(name) => name.to_string_lossy(),
+      None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a file name")),
+   };
+
+   let tmp = format!(".{}.minigrep-{}.tmp", name, process::id());
+
+   Ok(path.with_file_name(tmp))
+}
+
+fn write_temp(path: &Path, tmp: &Path, contents: &[u8]) -> io::Result<()> {
+   let mut file = OpenOptions::new().write(true).create_new(true).open(tmp)?;
+
+   file.write_all(contents)?;
+   file.set_permissions(fs::metadata(path)?.permissions())?;
+   file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use tempfile::TempDir;
+
+   #[test]
+   fn keeps_line_terminators() {
+      let matcher = Matcher::literal(&["duct"], true);
+      let contents = "Rust:\r\nsafe, fast, productive.\nDuct tape.";
+
+      let (replaced, changes) = replace_all(&matcher, contents, "DUCT");
+
+      assert_eq!("Rust:\r\nsafe, fast, proDUCTive.\nDuct tape.", replaced);
+      assert_eq!(
+         vec![Change {
+            line_number: 2,
+            old: "safe, fast, productive.".to_string(),
+            new: "safe, fast, proDUCTive.".to_string(),
+         }],
+         changes
+      );
+   }
+
+   #[test]
+   fn rewrites_file_with_backup() {
+      let dir = TempDir::new().unwrap();
+      let path = dir.path().join("poem.txt");
+      fs::write(&path, "old\n").unwrap();
+
+      write_atomic(&path, b"new\n", Some(".bak")).unwrap();
+
+      assert_eq!("new\n", fs::read_to_string(&path).unwrap());
+      assert_eq!("old\n", fs::read_to_string(dir.path().join("poem.txt.bak")).unwrap());
+      assert_eq!(2, fs::read_dir(dir.path()).unwrap().count());
+   }
+}
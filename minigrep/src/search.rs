@@ -0,0 +1,286 @@
+use std::io::{self, BufRead};
+use std::iter::Enumerate;
+use std::ops::Range;
+use std::str::Lines;
+use std::vec;
+
+use aho_corasick::PatternMatch;
+use matcher::Matcher;
+
+/// A single match found by a `Searcher`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match<'a> {
+   /// Line number of the match, starting at 1.
+   pub line_number: usize,
+   /// Byte range of the match within `line`.
+   pub byte_range: Range<usize>,
+   /// Index of the pattern that matched.
+   pub pattern: usize,
+   /// The whole line, without its terminator.
+   pub line: &'a str,
+}
+
+/// Searches text line by line for the patterns of a `Matcher`, or the text
+/// as a whole in multiline mode.
+///
+/// ```
+/// use minigrep::{Matcher, Searcher};
+///
+/// let searcher = Searcher::new(Matcher::literal(&["duct"], true));
+/// let found: Vec<_> = searcher.search_str("Rust:\nsafe, fast, productive.")
+///    .map(|m| (m.line_number, m.byte_range))
+///    .collect();
+///
+/// assert_eq!(vec![(2, 15..19)], found);
+/// ```
+pub struct Searcher {
+   matcher: Matcher,
+   multiline: bool,
+}
+
+impl Searcher {
+   pub fn new(matcher: Matcher) -> Searcher {
+      Searcher {
+         matcher,
+         multiline: false,
+      }
+   }
+
+   /// In multiline mode matches may span line breaks. The `line` of such a
+   /// match holds all the lines it touches, and is shared by the other
+   /// matches on those lines.
+   pub fn multiline(mut self, multiline: bool) -> Searcher {
+      self.multiline = multiline;
+
+      self
+   }
+
+   pub fn matcher(&self) -> &Matcher {
+      &self.matcher
+   }
+
+   /// Returns an iterator over the matches in `text`.
+   pub fn search_str<'a>(&'a self, text: &'a str) -> Matches<'a> {
+      if self.multiline {
+         let matches: Vec<Match> = find_multiline(&self.matcher, text).into_iter()
+            .flatten()
+            .collect();
+
+         return Matches {
+            matcher: &self.matcher,
+            lines: "".lines().enumerate(),
+            pending: matches.into_iter(),
+         };
+      }
+
+      Matches {
+         matcher: &self.matcher,
+         lines: text.lines().enumerate(),
+         pending: Vec::new().into_iter(),
+      }
+   }
+
+   /// Reads `reader` to the end, calling `sink` with the matches of every
+   /// line that has any. The matches borrow the line, which is only valid
+   /// for the duration of the call.
+   pub fn search_reader<R, F>(&self, mut reader: R, mut sink: F) -> io::Result<()>
+      where
+         R: BufRead,
+         F: FnMut(&[Match])
+   {
+      let mut buffer = String::new();
+
+      if self.multiline {
+         reader.read_to_string(&mut buffer)?;
+
+         for matches in find_multiline(&self.matcher, &buffer) {
+            sink(&matches);
+         }
+
+         return Ok(());
+      }
+      let mut line_number = 0;
+
+      loop {
+         buffer.clear();
+
+         if reader.read_line(&mut buffer)? == 0 {
+            return Ok(());
+         }
+
+         line_number += 1;
+
+         let line = match buffer.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => &buffer,
+         };
+
+         let matches = find_matches(&self.matcher, line_number, line);
+
+         if !matches.is_empty() {
+            sink(&matches);
+         }
+      }
+   }
+}
+
+fn find_matches<'a>(matcher: &Matcher, line_number: usize, line: &'a str) -> Vec<Match<'a>> {
+   matcher.find_all(line).into_iter()
+      .map(|m| Match {
+         line_number,
+         byte_range: m.start..m.end,
+         pattern: m.pattern,
+         line,
+      })
+      .collect()
+}
+
+/// Lines touched by a group of matches, and the matches with their line
+/// numbers.
+type Group = (Range<usize>, Vec<(usize, PatternMatch)>);
+
+/// Finds the matches in the whole of `text`, grouped by the lines they
+/// touch. Matches on overlapping lines share one `line`.
+fn find_multiline<'a>(matcher: &Matcher, text: &'a str) -> Vec<Vec<Match<'a>>> {
+   let mut groups: Vec<Group> = Vec::new();
+   let mut line_number = 1;
+   let mut counted = 0;
+
+   for m in matcher.find_all(text) {
+      line_number += text[counted..m.start].matches('\n').count();
+      counted = m.start;
+
+      let start = text[..m.start].rfind('\n').map_or(0, |i| i + 1);
+      let end = line_end(text, m.start, m.end);
+
+      match groups.last_mut() {
+         Some(&mut (ref mut lines, ref mut matches)) if start < lines.end => {
+            lines.end = lines.end.max(end);
+            matches.push((line_number, m));
+         },
+         _ => groups.push((start..end, vec![(line_number, m)])),
+      }
+   }
+
+   groups.into_iter()
+      .map(|(lines, matches)| {
+         matches.into_iter()
+            .map(|(line_number, m)| Match {
+               line_number,
+               byte_range: m.start - lines.start..m.end - lines.start,
+               pattern: m.pattern,
+               line: &text[lines.clone()],
+            })
+            .collect()
+      })
+      .collect()
+}
+
+/// Returns the end of the line a match ends on, without its terminator
+/// unless the match itself ends with it.
+fn line_end(text: &str, start: usize, end: usize) -> usize {
+   if end > start && text[..end].ends_with('\n') {
+      return end;
+   }
+
+   let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+
+   if line_end > end && text[..line_end].ends_with('\r') {
+      line_end - 1
+   } else {
+      line_end
+   }
+}
+
+/// Iterator over the matches in a string, created by `Searcher::search_str`.
+pub struct Matches<'a> {
+   matcher: &'a Matcher,
+   lines: Enumerate<Lines<'a>>,
+   pending: vec::IntoIter<Match<'a>>,
+}
+
+impl<'a> Iterator for Matches<'a> {
+   type Item = Match<'a>;
+
+   fn next(&mut self) -> Option<Match<'a>> {
+      loop {
+         if let Some(m) = self.pending.next() {
+            return Some(m);
+         }
+
+         let (index, line) = self.lines.next()?;
+
+         self.pending = find_matches(self.matcher, index + 1, line).into_iter();
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   const CONTENTS: &str = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+   #[test]
+   fn iterates_matches() {
+      let searcher = Searcher::new(Matcher::literal(&["duct", "t"], false));
+
+      let found: Vec<(usize, Range<usize>, usize)> = searcher.search_str(CONTENTS)
+         .map(|m| (m.line_number, m.byte_range, m.pattern))
+         .collect();
+
+      assert_eq!(
+         vec![
+            (1, 3..4, 1),
+            (2, 9..10, 1),
+            (2, 15..19, 0),
+            (3, 5..6, 1),
+            (4, 0..4, 0),
+            (4, 5..6, 1),
+         ],
+         found
+      );
+   }
+
+   #[test]
+   fn sink_receives_lines() {
+      let searcher = Searcher::new(Matcher::literal(&["a"], true));
+      let mut found = Vec::new();
+
+      searcher.search_reader(CONTENTS.as_bytes(), |matches| {
+         found.push((matches[0].line_number, matches[0].line.to_string(), matches.len()));
+      }).unwrap();
+
+      assert_eq!(
+         vec![
+            (2, "safe, fast, productive.".to_string(), 2),
+            (4, "Duct tape.".to_string(), 1),
+         ],
+         found
+      );
+   }
+
+   #[test]
+   fn multiline_matches() {
+      let matcher = Matcher::regex(&[r"fast,\s+\w+", "tape", "Pick"], true).unwrap();
+      let searcher = Searcher::new(matcher).multiline(true);
+      let text = "Rust:\nsafe, fast,\nproductive.\nPick three.\r\nDuct tape.\n";
+
+      let found: Vec<(usize, Range<usize>, &str)> = searcher.search_str(text)
+         .map(|m| (m.line_number, m.byte_range, m.line))
+         .collect();
+
+      assert_eq!(
+         vec![
+            (2, 6..22, "safe, fast,\nproductive."),
+            (4, 0..4, "Pick three."),
+            (5, 5..9, "Duct tape."),
+         ],
+         found
+      );
+   }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glob::Glob;
+
+/// Built-in file types for `--type` and `--type-not`, as file name globs.
+pub static FILE_TYPES: &[(&str, &[&str])] = &[
+   ("c", &["*.c", "*.h"]),
+   ("cargo", &["Cargo.toml", "Cargo.lock"]),
+   ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx"]),
+   ("css", &["*.css"]),
+   ("glsl", &["*.glsl", "*.vert", "*.frag", "*.comp"]),
+   ("html", &["*.html", "*.htm"]),
+   ("java", &["*.java"]),
+   ("js", &["*.js", "*.mjs"]),
+   ("json", &["*.json"]),
+   ("log", &["*.log"]),
+   ("markdown", &["*.md", "*.markdown"]),
+   ("py", &["*.py"]),
+   ("rust", &["*.rs"]),
+   ("sh", &["*.sh", "*.bash"]),
+   ("toml", &["*.toml"]),
+   ("ts", &["*.ts", "*.tsx"]),
+   ("txt", &["*.txt"]),
+   ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// Returns the globs of a built-in file type.
+pub fn file_type(name: &str) -> Option<&'static [&'static str]> {
+   FILE_TYPES.iter()
+      .find(|&&(type_name, _)| type_name == name)
+      .map(|&(_, globs)| globs)
+}
+
+/// Decides which files found while walking a directory are searched.
+///
+/// A file is searched when it matches one of the `-g` globs and one of the
+/// `--type` types (where there are any), and no `-g '!...'` glob and no
+/// `--type-not` type.
+pub struct Filter {
+   includes: Vec<Glob>,
+   excludes: Vec<Glob>,
+   types: Vec<Glob>,
+   types_not: Vec<Glob>,
+}
+
+impl Filter {
+   /// Builds the filter, failing on an unknown file type.
+   pub fn new(globs: &[String], types: &[String], types_not: &[String]) -> Result<Filter, String> {
+      let mut includes = Vec::new();
+      let mut excludes = Vec::new();
+
+      for glob in globs {
+         match glob.strip_prefix('!') {
+            Some(glob) => excludes.push(Glob::new(glob)),
+            None => includes.push(Glob::new(glob)),
+         }
+      }
+
+      Ok(Filter {
+         includes,
+         excludes,
+         types: type_globs(types)?,
+         types_not: type_globs(types_not)?,
+      })
+   }
+
+   /// Whether to search the file at `path`, relative to the walked directory.
+   pub fn allows_file(&self, path: &str) -> bool {
+      let any = |globs: &[Glob]| globs.iter().any(|glob| glob.is_match(path));
+
+      (self.includes.is_empty() || any(&self.includes))
+         && (self.types.is_empty() || any(&self.types))
+         && !any(&self.excludes)
+         && !any(&self.types_not)
+   }
+
+   /// Whether to descend into the directory at `path`, relative to the
+   /// walked directory.
+   pub fn allows_dir(&self, path: &str) -> bool {
+      !self.excludes.iter().any(|glob| glob.is_match(path) || glob.matches_all_below(path))
+   }
+}
+
+fn type_globs(names: &[String]) -> Result<Vec<Glob>, String> {
+   let mut globs = Vec::new();
+
+   for name in names {
+      match file_type(name) {
+         Some(type_globs) => globs.extend(type_globs.iter().map(|glob| Glob::new(glob))),
+         None => return Err(format!("unknown file type '{}'", name)),
+      }
+   }
+
+   Ok(globs)
+}
+
+/// Collects the files below `root` that pass `filter`, in sorted order.
+/// Hidden files and directories, whose names start with '.', are skipped.
+///
+/// Symlinks are followed, but every directory is walked only once, so links
+/// back up the tree end. Entries that cannot be read below `root`, such as
+/// dangling links, are reported and skipped.
+pub fn walk(root: &Path, filter: &Filter, files: &mut Vec<PathBuf>) -> io::Result<()> {
+   let mut visited = HashSet::new();
+   visited.insert(fs::canonicalize(root)?);
+
+   walk_dir(root, "", filter, &mut visited, files)
+}
+
+fn walk_dir(
+   dir: &Path,
+   relative: &str,
+   filter: &Filter,
+   visited: &mut HashSet<PathBuf>,
+   files: &mut Vec<PathBuf>
+) -> io::Result<()> {
+   let mut entries = Vec::new();
+
+   for entry in fs::read_dir(dir)? {
+      entries.push(entry?);
+   }
+
+   entries.sort_by_key(|entry| entry.file_name());
+
+   for entry in entries {
+      let name = entry.file_name();
+      let name = name.to_string_lossy();
+
+      if name.starts_with('.') {
+         continue;
+      }
+
+      let path = if relative.is_empty() {
+         name.into_owned()
+      } else {
+         format!("{}/{}", relative, name)
+      };
+
+      // Follows symlinks, so linked files and directories are searched too.
+      let file_type = match fs::metadata(entry.path()) {
+         Ok(metadata) => metadata.file_type(),
+         Err(e) => {
+            skip(&entry.path(), &e);
+            continue;
+         },
+      };
+
+      if file_type.is_dir() {
+         if !filter.allows_dir(&path) {
+            continue;
+         }
+
+         match fs::canonicalize(entry.path()) {
+            Ok(canonical) => if !visited.insert(canonical) {
+               continue;
+            },
+            Err(e) => {
+               skip(&entry.path(), &e);
+               continue;
+            },
+         }
+
+         if let Err(e) = walk_dir(&entry.path(), &path, filter, visited, files) {
+            skip(&entry.path(), &e);
+         }
+      } else if file_type.is_file() && filter.allows_file(&path) {
+         files.push(entry.path());
+      }
+   }
+
+   Ok(())
+}
+
+/// Reports a file or directory below a walked directory that is left out.
+pub fn skip(path: &Path, e: &io::Error) {
+   eprintln!("minigrep: skipping {}: {}", path.display(), e);
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use tempfile::TempDir;
+
+   fn strings(items: &[&str]) -> Vec<String> {
+      items.iter().map(|item| item.to_string()).collect()
+   }
+
+   #[test]
+   fn filters_by_type_and_glob() {
+      let filter = Filter::new(&strings(&["!target/**"]), &strings(&["rust", "cargo"]), &[]).unwrap();
+
+      assert!(filter.allows_file("hello/src/lib.rs"));
+      assert!(filter.allows_file("hello/Cargo.toml"));
+      assert!(!filter.allows_file("hello/hello.html"));
+      assert!(!filter.allows_file("hello/target/debug/build/out.rs"));
+      assert!(!filter.allows_dir("hello/target"));
+      assert!(filter.allows_dir("hello/src"));
+
+      let filter = Filter::new(&strings(&["*.toml"]), &[], &strings(&["cargo"])).unwrap();
+
+      assert!(filter.allows_file("rustfmt.toml"));
+      assert!(!filter.allows_file("Cargo.toml"));
+
+      assert!(Filter::new(&[], &strings(&["cobol"]), &[]).is_err());
+   }
+
+   #[test]
+   fn walks_sorted_skipping_hidden() {
+      let dir = TempDir::new().unwrap();
+      let root = dir.path();
+
+      for dir in &["src", "target/debug", ".git"] {
+         fs::create_dir_all(root.join(dir)).unwrap();
+      }
+
+      for file in &["src/main.rs", "src/lib.rs", "Cargo.toml", "target/debug/out.rs", ".git/HEAD"] {
+         fs::write(root.join(file), "").unwrap();
+      }
+
+      let filter = Filter::new(&strings(&["!target/**"]), &[], &[]).unwrap();
+      let mut files = Vec::new();
+
+      walk(root, &filter, &mut files).unwrap();
+
+      let files: Vec<PathBuf> = files.iter()
+         .map(|file| file.strip_prefix(root).unwrap().to_path_buf())
+         .collect();
+
+      assert_eq!(
+         vec![PathBuf::from("Cargo.toml"), PathBuf::from("src/lib.rs"), PathBuf::from("src/main.rs")],
+         files
+      );
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn survives_symlink_cycles_and_dangling_links() {
+      use std::os::unix::fs::symlink;
+
+      let dir = TempDir::new().unwrap();
+      let root = dir.path();
+
+      fs::create_dir_all(root.join("logs/old")).unwrap();
+      fs::write(root.join("logs/app.log"), "").unwrap();
+      symlink(root, root.join("logs/old/up")).unwrap();
+      symlink(root.join("logs"), root.join("logs-link")).unwrap();
+      symlink(root.join("missing.log"), root.join("logs/broken.log")).unwrap();
+
+      let mut files = Vec::new();
+
+      walk(root, &Filter::new(&[], &[], &[]).unwrap(), &mut files).unwrap();
+
+      assert_eq!(vec![root.join("logs/app.log")], files);
+   }
+}